@@ -0,0 +1,47 @@
+//! The cached engine against the interpreter, running bundled ROMs headless.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use r_chip_8::{Cpu, Engine};
+use std::fs;
+
+/// Instructions per iteration
+const STEPS: u64 = 100_000;
+
+fn load(rom: &[u8], engine: Engine) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_seed(0xC8);
+    cpu.set_engine(engine);
+    cpu.load_rom_bytes(rom).unwrap();
+    cpu
+}
+
+fn run(mut cpu: Cpu) -> Cpu {
+    for step in 0..STEPS {
+        cpu.step();
+        // 16 instructions per frame, about 1000 per second
+        if step % 16 == 15 {
+            cpu.end_frame();
+        }
+    }
+    cpu
+}
+
+fn engines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine");
+    group.throughput(Throughput::Elements(STEPS));
+    for name in ["INVADERS", "TETRIS", "BLITZ", "BRIX"] {
+        let rom = fs::read(format!("rom/c8games/{}", name)).unwrap();
+        for (label, engine) in [
+            ("interpreter", Engine::Interpreter),
+            ("cached", Engine::Cached),
+        ] {
+            group.bench_with_input(BenchmarkId::new(label, name), &rom, |b, rom| {
+                b.iter_batched(|| load(rom, engine), run, BatchSize::SmallInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! The interpreter core, headless: instructions per second on bundled ROMs
+//! and on DRW-heavy and ALU-heavy loops, and the cost of save states.
+//!
+//! Run without the `trace` feature, which prints every instruction.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use r_chip_8::Cpu;
+use std::fs;
+
+/// Instructions per iteration
+const CYCLES: u32 = 100_000;
+const IPF: u32 = 20;
+
+// 200: LD V0, 01
+// 202: LD V1, 03
+// 204: ADD V0, V1
+// 206: SUB V1, V0
+// 208: XOR V0, V1
+// 20A: SHR V1
+// 20C: ADD V0, 0F
+// 20E: JP 204
+const ALU_LOOP: [u8; 16] = [
+    0x60, 0x01, 0x61, 0x03, 0x80, 0x14, 0x81, 0x05, 0x80, 0x13, 0x81, 0x06, 0x70, 0x0F, 0x12, 0x04,
+];
+
+// 200: LD I, 20A
+// 202: DRW V0, V1, 5
+// 204: ADD V0, 03
+// 206: ADD V1, 02
+// 208: JP 202
+// 20A: a 0 of the font
+const DRW_LOOP: [u8; 15] = [
+    0xA2, 0x0A, 0xD0, 0x15, 0x70, 0x03, 0x71, 0x02, 0x12, 0x02, 0xF0, 0x90, 0x90, 0x90, 0xF0,
+];
+
+fn load(rom: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_seed(0xC8);
+    cpu.load_rom_bytes(rom).unwrap();
+    cpu
+}
+
+/// Runs `CYCLES` instructions: the default quirks never end a frame early
+fn run(mut cpu: Cpu) -> Cpu {
+    for _ in 0..CYCLES / IPF {
+        cpu.run_frame(IPF);
+    }
+    cpu
+}
+
+fn roms(c: &mut Criterion) {
+    if cfg!(feature = "trace") {
+        eprintln!("warning: the trace feature is enabled, the results measure printing");
+    }
+    let mut group = c.benchmark_group("rom");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    for name in ["INVADERS", "TETRIS", "BLITZ", "BRIX", "PONG", "UFO"] {
+        let rom = fs::read(format!("rom/c8games/{}", name)).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &rom, |b, rom| {
+            b.iter_batched(|| load(rom), run, BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+fn workloads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("workload");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    for (name, rom) in [("alu", &ALU_LOOP[..]), ("drw", &DRW_LOOP[..])] {
+        group.bench_with_input(BenchmarkId::from_parameter(name), rom, |b, rom| {
+            b.iter_batched(|| load(rom), run, BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+fn save_states(c: &mut Criterion) {
+    let rom = fs::read("rom/c8games/INVADERS").unwrap();
+    let mut cpu = load(&rom);
+    for _ in 0..120 {
+        cpu.run_frame(IPF);
+    }
+    let state = cpu.save_state();
+
+    let mut group = c.benchmark_group("save_state");
+    group.throughput(Throughput::Bytes(state.len() as u64));
+    group.bench_function("save", |b| b.iter(|| cpu.save_state()));
+    group.bench_function("load", |b| {
+        let mut restored = Cpu::new();
+        b.iter(|| restored.load_state(&state).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, roms, workloads, save_states);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! A single opcode executed against arbitrary registers, keys and memory
+//! around I, by both engines: nothing panics, and both end in the same state.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use r_chip_8::memory::SIZE;
+use r_chip_8::{Cpu, Engine, KeyState, Quirks};
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    opcode: u16,
+    pc: u16,
+    i: u16,
+    registers: [u8; 16],
+    dt: u8,
+    st: u8,
+    keys: u16,
+    /// Subroutines called before the opcode, to fill the stack
+    calls: u8,
+    profile: u8,
+    hires: bool,
+    /// Written from I on, for DRW and Fx65
+    data: [u8; 32],
+}
+
+fn cpu(input: &Input, engine: Engine) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_quirks([Quirks::VIP, Quirks::SCHIP, Quirks::MODERN][input.profile as usize % 3]);
+    cpu.set_seed(0);
+    cpu.set_engine(engine);
+
+    // CALLs to the next instruction, then HIGH and a jump to the opcode
+    let pc = input.pc as usize % SIZE;
+    let mut setup = Vec::new();
+    for n in 0..input.calls as usize % 20 {
+        let next = 0x202 + 2 * n as u16;
+        setup.extend_from_slice(&(0x2000 | next).to_be_bytes());
+    }
+    if input.hires {
+        setup.extend_from_slice(&[0x00, 0xFF]);
+    }
+    setup.extend_from_slice(&(0x1000 | pc as u16).to_be_bytes());
+    cpu.load_rom_bytes(&setup).unwrap();
+    for _ in 0..setup.len() / 2 {
+        cpu.step();
+    }
+
+    let memory = cpu.memory_mut();
+    for (offset, byte) in input.data.iter().enumerate() {
+        memory.load((input.i as usize + offset) % SIZE, &[*byte]);
+    }
+    let [high, low] = input.opcode.to_be_bytes();
+    memory.load(pc, &[high]);
+    memory.load((pc + 1) % SIZE, &[low]);
+
+    cpu.set_i(input.i);
+    for (x, value) in input.registers.iter().enumerate() {
+        cpu.set_register(x, *value);
+    }
+    cpu.set_dt(input.dt);
+    cpu.set_st(input.st);
+    cpu.set_keys(KeyState(input.keys));
+    cpu
+}
+
+fuzz_target!(|input: Input| {
+    let mut interpreter = cpu(&input, Engine::Interpreter);
+    let mut cached = cpu(&input, Engine::Cached);
+    interpreter.step();
+    cached.step();
+    assert_eq!(interpreter.save_state(), cached.save_state());
+});
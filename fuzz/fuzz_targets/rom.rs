@@ -0,0 +1,35 @@
+//! Arbitrary bytes as a ROM, run for a few frames by both engines: nothing
+//! panics, and both end in the same state.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use r_chip_8::{Cpu, Engine, KeyState, Quirks};
+
+const FRAMES: u32 = 20;
+const IPF: u32 = 50;
+
+fuzz_target!(|input: (u8, u16, &[u8])| {
+    let (profile, keys, rom) = input;
+    let quirks = [Quirks::VIP, Quirks::SCHIP, Quirks::MODERN][profile as usize % 3];
+
+    let mut cpus = [Engine::Interpreter, Engine::Cached].map(|engine| {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(quirks);
+        cpu.set_seed(0);
+        cpu.set_engine(engine);
+        // Too long for memory is an error, not a panic
+        let _ = cpu.load_rom_bytes(rom);
+        cpu
+    });
+    for frame in 0..FRAMES {
+        for cpu in cpus.iter_mut() {
+            // Keys are held for the second half of the run
+            if frame == FRAMES / 2 {
+                cpu.set_keys(KeyState(keys));
+            }
+            cpu.run_frame(IPF);
+        }
+    }
+    assert_eq!(cpus[0].save_state(), cpus[1].save_state());
+});
@@ -0,0 +1,44 @@
+//! The save state loader on a valid state with arbitrary bytes patched and
+//! cut off: nothing panics, and a state that loads saves back identically and
+//! runs.
+
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use r_chip_8::Cpu;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// Offsets into the state and the bytes to write there
+    patches: Vec<(u16, u8)>,
+    /// Bytes to cut off the end
+    truncate: u16,
+    /// Bytes to append
+    extra: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut cpu = Cpu::new();
+    cpu.load_rom_bytes(include_bytes!("../../rom/IBMLogo.ch8"))
+        .unwrap();
+    for _ in 0..10 {
+        cpu.run_frame(10);
+    }
+    let mut state = cpu.save_state();
+    for (offset, byte) in &input.patches {
+        if let Some(slot) = state.get_mut(*offset as usize) {
+            *slot = *byte;
+        }
+    }
+    state.truncate(state.len().saturating_sub(input.truncate as usize));
+    state.extend_from_slice(&input.extra);
+
+    let mut loaded = Cpu::new();
+    if loaded.load_state(&state).is_ok() {
+        assert_eq!(loaded.save_state(), state);
+        for _ in 0..10 {
+            loaded.run_frame(10);
+        }
+    }
+});
@@ -0,0 +1,183 @@
+//! Rendering the buzzer to PCM samples and WAV files.
+//!
+//! Audio is generated per emulated frame rather than per host callback: every
+//! frame is exactly `SAMPLES_PER_FRAME` samples long, so a recording stays in
+//! step with the frames it accompanies and the same ROM and inputs always
+//! produce the same samples. That makes the output suitable for golden-file
+//! comparisons in headless tests.
+
+use crate::scheduler::FRAME_RATE;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// 44100 / 60: a whole number, so frames never drift against the samples.
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FRAME_RATE) as usize;
+
+/// Peak amplitude of the generated waves, a quarter of full scale
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+/// What the buzzer sounds like while the sound timer is non-zero
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// A square wave of the given frequency in Hz
+    Square(f64),
+    /// An XO-CHIP audio pattern: 128 one-bit samples, most significant bit
+    /// first, looped at `4000 * 2^((pitch - 64) / 48)` bits per second.
+    Pattern { pattern: [u8; 16], pitch: u8 },
+}
+
+impl Default for Waveform {
+    fn default() -> Waveform {
+        Waveform::Square(440.0)
+    }
+}
+
+impl Waveform {
+    /// Length of one period, in the unit the phase advances in
+    fn period(&self) -> f64 {
+        match self {
+            Waveform::Square(_) => 1.0,
+            Waveform::Pattern { .. } => 128.0,
+        }
+    }
+
+    /// How far the phase advances per sample
+    fn step(&self) -> f64 {
+        match self {
+            Waveform::Square(frequency) => frequency / SAMPLE_RATE as f64,
+            Waveform::Pattern { pitch, .. } => {
+                let rate = 4000.0 * 2f64.powf((*pitch as f64 - 64.0) / 48.0);
+                rate / SAMPLE_RATE as f64
+            }
+        }
+    }
+
+    fn level(&self, phase: f64) -> bool {
+        match self {
+            Waveform::Square(_) => phase < 0.5,
+            Waveform::Pattern { pattern, .. } => {
+                let bit = phase as usize;
+                pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+            }
+        }
+    }
+}
+
+/// Generates the buzzer output frame by frame.
+///
+/// The phase carries over between frames, so a tone that lasts several
+/// frames is one continuous wave.
+#[derive(Debug, Clone, Default)]
+pub struct Synth {
+    waveform: Waveform,
+    phase: f64,
+}
+
+impl Synth {
+    pub fn new(waveform: Waveform) -> Synth {
+        Synth {
+            waveform,
+            phase: 0.0,
+        }
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    /// Changes the waveform from the next frame on. The phase restarts.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+        self.phase = 0.0;
+    }
+
+    /// Appends one frame of samples to `out`: the waveform if the buzzer is
+    /// `on`, silence otherwise.
+    pub fn render_frame(&mut self, on: bool, out: &mut Vec<i16>) {
+        if !on {
+            out.extend(std::iter::repeat_n(0, SAMPLES_PER_FRAME));
+            return;
+        }
+        let period = self.waveform.period();
+        let step = self.waveform.step();
+        for _ in 0..SAMPLES_PER_FRAME {
+            let level = self.waveform.level(self.phase);
+            out.push(if level { AMPLITUDE } else { -AMPLITUDE });
+            self.phase = (self.phase + step) % period;
+        }
+    }
+}
+
+/// Collects the audio of a session, one `frame` call per emulated frame.
+#[derive(Debug, Clone, Default)]
+pub struct AudioRecorder {
+    synth: Synth,
+    samples: Vec<i16>,
+}
+
+impl AudioRecorder {
+    pub fn new(waveform: Waveform) -> AudioRecorder {
+        AudioRecorder {
+            synth: Synth::new(waveform),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn synth_mut(&mut self) -> &mut Synth {
+        &mut self.synth
+    }
+
+    /// Adds a frame during which the buzzer was `on` (see `Cpu::buzzer`).
+    pub fn frame(&mut self, on: bool) {
+        self.synth.render_frame(on, &mut self.samples);
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn write_wav<W: Write>(&self, out: W) -> io::Result<()> {
+        write_wav(out, &self.samples)
+    }
+
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_wav(&mut out)?;
+        out.flush()
+    }
+}
+
+/// Writes `samples` as a 16-bit mono PCM WAV file at `SAMPLE_RATE`.
+pub fn write_wav<W: Write>(mut out: W, samples: &[i16]) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let data_size = u32::try_from(samples.len() * block_align as usize)
+        .ok()
+        .filter(|size| *size <= u32::MAX - 36)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too long for a WAV file"))?;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&CHANNELS.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
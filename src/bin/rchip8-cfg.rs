@@ -0,0 +1,83 @@
+//! Prints the control-flow graph of a ROM as Graphviz DOT or JSON, and its
+//! warnings on stderr.
+
+use r_chip_8::cfg::Cfg;
+use r_chip_8::memory;
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: rchip8-cfg [OPTIONS] ROM
+
+Options:
+    --json       Print JSON instead of Graphviz DOT
+    --start <ADDR>
+                 Hexadecimal load and start address (default: 200)
+    -h, --help   Print this message
+
+Example: rchip8-cfg rom/c8games/BLINKY | dot -Tsvg > blinky.svg";
+
+enum Format {
+    Dot,
+    Json,
+}
+
+struct Options {
+    rom: String,
+    format: Format,
+    start: u16,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut rom = None;
+        let mut format = Format::Dot;
+        let mut start = memory::PROGRAM.start;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => format = Format::Json,
+                "--start" => {
+                    let value = args.next().ok_or("--start requires a value")?;
+                    start = u16::from_str_radix(value.strip_prefix("0x").unwrap_or(&value), 16)
+                        .ok()
+                        .filter(|address| (*address as usize) < memory::SIZE)
+                        .ok_or_else(|| format!("invalid --start address: {}", value))?;
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+                _ => rom = Some(arg),
+            }
+        }
+
+        Ok(Options {
+            rom: rom.ok_or("missing ROM")?,
+            format,
+            start,
+        })
+    }
+}
+
+fn main() {
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("{}\n\n{}", msg, USAGE);
+        process::exit(2);
+    });
+
+    let rom = fs::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("{}: {}", options.rom, e);
+        process::exit(1);
+    });
+    let cfg = Cfg::build(&rom, options.start);
+    for warning in &cfg.warnings {
+        eprintln!("{}: {}", options.rom, warning);
+    }
+    match options.format {
+        Format::Dot => print!("{}", cfg.to_dot()),
+        Format::Json => print!("{}", cfg.to_json()),
+    }
+}
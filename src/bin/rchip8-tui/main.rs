@@ -0,0 +1,180 @@
+//! Terminal frontend: renders the display with Unicode block or braille
+//! characters, next to a panel with the CPU state.
+
+mod panel;
+mod terminal;
+
+use r_chip_8::coverage::Coverage;
+use r_chip_8::profiler::Profiler;
+use r_chip_8::romdb::Database;
+use r_chip_8::{Cpu, Machine, Palette, Quirks};
+use std::env;
+use std::fs;
+use std::process;
+use terminal::{Glyphs, TerminalHost};
+
+const DEFAULT_IPF: u32 = 10;
+
+const USAGE: &str = "\
+Usage: rchip8-tui [OPTIONS] ROM
+
+Options:
+    --ipf <N>    Instructions executed per 60 Hz frame (default: from the ROM
+                 database, else 10)
+    --quirks <PROFILE>
+                 Interpreter quirks: vip, schip or modern (default: from the
+                 ROM database, else modern)
+    --palette <NAME>
+                 Colours: classic, amber, lcd, high-contrast or xo (default:
+                 from the ROM database, else classic)
+    --braille    Draw with braille patterns (2x4 pixels per character) instead
+                 of half blocks (1x2 pixels per character)
+    --coverage <FILE>
+                 Write an annotated disassembly with instruction and skip counts
+                 to FILE, and an lcov tracefile to FILE.info, when quitting
+    --profile <FILE>
+                 Write a profile (hot spots, subroutines, delay timer waits) to
+                 FILE, and folded stacks for flamegraphs to FILE.folded, when
+                 quitting
+    -h, --help   Print this message
+
+Keys: 1234/QWER/ASDF/ZXCV, Esc to quit";
+
+struct Options {
+    rom: String,
+    ipf: Option<u32>,
+    quirks: Option<Quirks>,
+    palette: Option<Palette>,
+    glyphs: Glyphs,
+    coverage: Option<String>,
+    profile: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            ipf: None,
+            quirks: None,
+            palette: None,
+            glyphs: Glyphs::HalfBlocks,
+            coverage: None,
+            profile: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ipf" => {
+                    let value = args.next().ok_or("--ipf requires a value")?;
+                    options.ipf = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --ipf value: {}", value))?,
+                    );
+                }
+                "--quirks" => {
+                    let value = args.next().ok_or("--quirks requires a value")?;
+                    options.quirks = Some(
+                        Quirks::from_name(&value)
+                            .ok_or_else(|| format!("unknown quirks profile: {}", value))?,
+                    );
+                }
+                "--palette" => {
+                    let value = args.next().ok_or("--palette requires a value")?;
+                    options.palette = Some(
+                        Palette::from_name(&value)
+                            .ok_or_else(|| format!("unknown palette: {}", value))?,
+                    );
+                }
+                "--braille" => options.glyphs = Glyphs::Braille,
+                "--coverage" => {
+                    options.coverage = Some(args.next().ok_or("--coverage requires a value")?);
+                }
+                "--profile" => {
+                    options.profile = Some(args.next().ok_or("--profile requires a value")?);
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+                _ => rom = Some(arg),
+            }
+        }
+
+        options.rom = rom.ok_or("missing ROM")?;
+        if options.coverage.is_some() && options.profile.is_some() {
+            return Err("--coverage and --profile cannot be combined".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn main() {
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("{}\n\n{}", msg, USAGE);
+        process::exit(2);
+    });
+
+    let mut cpu = Cpu::new();
+    let info = cpu.load_rom(&options.rom).unwrap_or_else(|e| {
+        eprintln!("{}: {}", options.rom, e);
+        process::exit(1);
+    });
+    let known = Database::builtin().lookup(&info.sha1);
+    let known = known.as_ref();
+    cpu.set_quirks(
+        options
+            .quirks
+            .or(known.and_then(|rom| rom.quirks))
+            .unwrap_or_default(),
+    );
+    let ipf = options
+        .ipf
+        .or(known.and_then(|rom| rom.ipf))
+        .unwrap_or(DEFAULT_IPF);
+    let palette = options
+        .palette
+        .or(known.and_then(|rom| rom.palette))
+        .unwrap_or_default();
+
+    let host = TerminalHost::new(palette, options.glyphs).unwrap_or_else(|e| {
+        eprintln!("cannot set up the terminal: {}", e);
+        process::exit(1);
+    });
+
+    let mut machine = Machine::new(cpu, host, ipf);
+    if options.coverage.is_some() {
+        machine.set_debugger(Some(Box::new(Coverage::new())));
+    }
+    if options.profile.is_some() {
+        machine.set_debugger(Some(Box::new(Profiler::new())));
+    }
+    while machine.tick() {
+        let lines = panel::lines(machine.cpu());
+        if machine.host_mut().draw_panel(&lines).is_err() {
+            break;
+        }
+    }
+
+    let mut errors = Vec::new();
+    if let Some(path) = &options.coverage {
+        let start = machine.cpu().start();
+        let coverage = machine.debugger_mut::<Coverage>().unwrap();
+        if let Err(e) = fs::read(&options.rom).and_then(|rom| coverage.save(path, &rom, start)) {
+            errors.push(format!("cannot write the coverage to {}: {}", path, e));
+        }
+    }
+    if let Some(path) = &options.profile {
+        let profiler = std::mem::take(machine.debugger_mut::<Profiler>().unwrap());
+        if let Err(e) = profiler.save(path, machine.cpu().memory()) {
+            errors.push(format!("cannot write the profile to {}: {}", path, e));
+        }
+    }
+    // Restore the terminal before reporting
+    drop(machine);
+    for error in errors {
+        eprintln!("{}", error);
+    }
+}
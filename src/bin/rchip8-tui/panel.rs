@@ -0,0 +1,55 @@
+//! The debugger side panel: registers, stack and disassembly around PC.
+
+use r_chip_8::memory;
+use r_chip_8::{Cpu, Instruction};
+
+/// Instructions disassembled before and after PC
+const CONTEXT: u16 = 4;
+
+pub fn lines(cpu: &Cpu) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC {:03X}   I {:03X}", cpu.pc(), cpu.i()),
+        format!("DT  {:02X}  ST  {:02X}", cpu.dt(), cpu.st()),
+        String::new(),
+    ];
+    if let Some(fault) = cpu.fault() {
+        lines.insert(2, format!("FAULT {}", fault));
+    }
+
+    for (row, regs) in cpu.registers().chunks(4).enumerate() {
+        let regs: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(col, v)| format!("V{:X} {:02X}", row * 4 + col, v))
+            .collect();
+        lines.push(regs.join("  "));
+    }
+
+    lines.push(String::new());
+    let backtrace = cpu.backtrace();
+    lines.push(format!("Stack ({}/{})", backtrace.len(), cpu.stack_depth()));
+    for (depth, frame) in backtrace.iter().enumerate().take(CONTEXT as usize) {
+        lines.push(format!("  {:2}  {}", backtrace.len() - 1 - depth, frame));
+    }
+
+    lines.push(String::new());
+    let start = cpu.pc().saturating_sub(CONTEXT * 2);
+    for addr in (start..=cpu.pc() + CONTEXT * 2).step_by(2) {
+        if addr + 1 >= memory::SIZE as u16 {
+            break;
+        }
+        let memory = cpu.memory();
+        let opcode =
+            (memory.read(addr as usize) as u16) << 8 | memory.read(addr as usize + 1) as u16;
+        let marker = if addr == cpu.pc() { '>' } else { ' ' };
+        lines.push(format!(
+            "{} {:03X}  {:04X}  {}",
+            marker,
+            addr,
+            opcode,
+            Instruction::decode(opcode)
+        ));
+    }
+
+    lines
+}
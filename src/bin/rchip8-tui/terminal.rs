@@ -0,0 +1,303 @@
+//! `Host` implementation on top of an ANSI terminal.
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use r_chip_8::{Framebuffer, Host, KeyState, Palette, Rgba};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::time::{Duration, Instant};
+
+/// Terminals without the kitty keyboard protocol never report key releases,
+/// only presses and auto-repeats. A key is therefore held for this long after
+/// it was first pressed, which bridges the usual delay before auto-repeat
+/// kicks in...
+const FIRST_HOLD: Duration = Duration::from_millis(500);
+
+/// ...and for this long after every repeat.
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+/// Columns between the display and the side panel
+const PANEL_GAP: u16 = 2;
+
+/// How display pixels are mapped onto character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    /// `▀` with the top pixel as foreground and the bottom one as background
+    /// colour: one cell per 1x2 pixels, in full colour.
+    HalfBlocks,
+    /// Braille patterns: one cell per 2x4 pixels, in the foreground colour only.
+    Braille,
+}
+
+impl Glyphs {
+    /// Pixels covered by one character cell, as (columns, rows)
+    fn cell(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlocks => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+}
+
+/// State of one keypad key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Held {
+    Up,
+    /// Down until the terminal reports the release
+    Down,
+    /// Down until the given time, unless the key repeats
+    Until(Instant),
+}
+
+pub struct TerminalHost {
+    out: BufWriter<Stdout>,
+    palette: Palette,
+    glyphs: Glyphs,
+    start: Instant,
+    held: [Held; 16],
+    /// Whether the terminal reports key releases
+    releases: bool,
+    running: bool,
+    /// Resolution drawn last, to clear the screen when it changes
+    resolution: (usize, usize),
+}
+
+impl TerminalHost {
+    /// Switches the terminal to raw mode and the alternate screen. Both are
+    /// restored when the host is dropped.
+    pub fn new(palette: Palette, glyphs: Glyphs) -> io::Result<TerminalHost> {
+        terminal::enable_raw_mode()?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        let mut out = BufWriter::new(io::stdout());
+        queue!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        if releases {
+            queue!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        out.flush()?;
+
+        Ok(TerminalHost {
+            out,
+            palette,
+            glyphs,
+            start: Instant::now(),
+            held: [Held::Up; 16],
+            releases,
+            running: true,
+            resolution: (0, 0),
+        })
+    }
+
+    /// Draws `lines` to the right of the display.
+    pub fn draw_panel(&mut self, lines: &[String]) -> io::Result<()> {
+        let (cols, _) = self.cells(self.resolution);
+        let column = cols as u16 + PANEL_GAP;
+        queue!(self.out, ResetColor)?;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(
+                self.out,
+                cursor::MoveTo(column, row as u16),
+                terminal::Clear(terminal::ClearType::UntilNewLine),
+                Print(line)
+            )?;
+        }
+        self.out.flush()
+    }
+
+    /// Size of the display in character cells, as (columns, rows)
+    fn cells(&self, (width, height): (usize, usize)) -> (usize, usize) {
+        let (cell_width, cell_height) = self.glyphs.cell();
+        (width / cell_width, height / cell_height)
+    }
+
+    fn draw(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        let resolution = framebuffer.resolution();
+        if resolution != self.resolution {
+            self.resolution = resolution;
+            queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        }
+
+        let (cols, rows) = self.cells(resolution);
+        match self.glyphs {
+            Glyphs::HalfBlocks => {
+                // Only emit colour changes
+                let mut colors = None;
+                for row in 0..rows {
+                    queue!(self.out, cursor::MoveTo(0, row as u16))?;
+                    for x in 0..cols {
+                        let top = framebuffer.pixel(x, row * 2) as usize;
+                        let bottom = framebuffer.pixel(x, row * 2 + 1) as usize;
+                        if colors != Some((top, bottom)) {
+                            colors = Some((top, bottom));
+                            queue!(
+                                self.out,
+                                SetForegroundColor(color(self.palette.colors[top])),
+                                SetBackgroundColor(color(self.palette.colors[bottom]))
+                            )?;
+                        }
+                        queue!(self.out, Print('▀'))?;
+                    }
+                }
+            }
+            Glyphs::Braille => {
+                queue!(
+                    self.out,
+                    SetForegroundColor(color(self.palette.colors[1])),
+                    SetBackgroundColor(color(self.palette.colors[0]))
+                )?;
+                for row in 0..rows {
+                    queue!(self.out, cursor::MoveTo(0, row as u16))?;
+                    for col in 0..cols {
+                        queue!(self.out, Print(braille(framebuffer, col * 2, row * 4)))?;
+                    }
+                }
+            }
+        }
+        queue!(self.out, ResetColor)?;
+        self.out.flush()
+    }
+
+    fn key_event(&mut self, key: KeyEvent) {
+        let quit = key.code == KeyCode::Esc
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL));
+        if quit {
+            self.running = false;
+            return;
+        }
+
+        let keypad = match key.code {
+            KeyCode::Char(c) => keypad(c.to_ascii_lowercase()),
+            _ => None,
+        };
+        let Some(k) = keypad else {
+            return;
+        };
+        let k = k as usize;
+
+        let now = Instant::now();
+        self.held[k] = match key.kind {
+            KeyEventKind::Release => Held::Up,
+            _ if self.releases => Held::Down,
+            KeyEventKind::Press if self.held[k] == Held::Up => Held::Until(now + FIRST_HOLD),
+            _ => Held::Until(now + REPEAT_HOLD),
+        };
+    }
+}
+
+impl Host for TerminalHost {
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        if framebuffer.is_dirty() || framebuffer.resolution() != self.resolution {
+            // A broken terminal is not worth crashing the interpreter for
+            let _ = self.draw(framebuffer);
+        }
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on {
+            let _ = queue!(self.out, Print('\x07'));
+            let _ = self.out.flush();
+        }
+    }
+
+    fn poll_input(&mut self) -> KeyState {
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) => self.key_event(key),
+                Ok(_) => {}
+                Err(_) => self.running = false,
+            }
+        }
+
+        let now = Instant::now();
+        let mut keys = KeyState::default();
+        for (k, held) in self.held.iter_mut().enumerate() {
+            match *held {
+                Held::Until(until) if until <= now => *held = Held::Up,
+                Held::Up => {}
+                _ => keys.press(k as u8),
+            }
+        }
+        keys
+    }
+
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn running(&self) -> bool {
+        self.running
+    }
+}
+
+impl Drop for TerminalHost {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = queue!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            self.out,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = self.out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn color([r, g, b, _]: Rgba) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+/// The braille pattern for the 2x4 pixels whose top left corner is (`x`, `y`)
+fn braille(framebuffer: &Framebuffer, x: usize, y: usize) -> char {
+    // Dot numbering of the Unicode braille block, by (column, row)
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+    let mut pattern = 0;
+    for (dx, column) in DOTS.iter().enumerate() {
+        for (dy, dot) in column.iter().enumerate() {
+            if framebuffer.pixel(x + dx, y + dy) != 0 {
+                pattern |= dot;
+            }
+        }
+    }
+    char::from_u32(0x2800 + pattern).unwrap()
+}
+
+/// Same layout as the SDL frontend: the left side of a QWERTY keyboard.
+fn keypad(c: char) -> Option<u8> {
+    match c {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
@@ -0,0 +1,114 @@
+//! A small RGBA drawing surface with a 3x5 pixel font, for the debug views.
+
+use r_chip_8::Rgba;
+
+/// Size of a character cell: the 3x5 glyph plus one pixel of spacing
+pub const CHAR_WIDTH: usize = 4;
+pub const CHAR_HEIGHT: usize = 6;
+
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize, background: Rgba) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            rgba: background.repeat(width * height),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    /// Fills a rectangle, clipped to the bitmap
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Rgba) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                let offset = (py * self.width + px) * 4;
+                self.rgba[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Draws `text` with its top left corner at (`x`, `y`). Lowercase letters
+    /// are drawn as uppercase; other unsupported characters as `?`.
+    pub fn text(&mut self, x: usize, y: usize, text: &str, color: Rgba) {
+        for (i, c) in text.chars().enumerate() {
+            let rows = glyph(c.to_ascii_uppercase());
+            for (dy, row) in rows.iter().enumerate() {
+                for dx in 0..3 {
+                    if row & (0b100 >> dx) != 0 {
+                        self.fill_rect(x + i * CHAR_WIDTH + dx, y + dy, 1, 1, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The 3x5 glyph of `c`, one row per byte, leftmost pixel in bit 2
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
@@ -0,0 +1,240 @@
+//! PNG screenshots and animated GIF/APNG recordings of the display.
+//!
+//! Images are rendered from the [`Framebuffer`] itself, so capturing works the
+//! same with every host, including headless ones.
+
+use crate::display::{Framebuffer, COLORS};
+use crate::palette::Palette;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Animation formats supported by `Recorder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Apng,
+}
+
+impl Format {
+    /// Picks the format from the file extension: `.gif`, or `.png`/`.apng`.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Format::Gif),
+            "png" | "apng" => Some(Format::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the framebuffer as a PNG image, every pixel scaled up to a
+/// `scale`x`scale` square.
+pub fn write_png<W: Write>(
+    out: W,
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: usize,
+) -> io::Result<()> {
+    let (width, height) = scaled_size(framebuffer, scale);
+    let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io_error)?;
+    let indexes = fitted_indexes(framebuffer, width, height);
+    writer
+        .write_image_data(&to_rgba(&indexes, palette))
+        .map_err(io_error)?;
+    writer.finish().map_err(io_error)
+}
+
+/// Saves the framebuffer as a PNG file, see `write_png`.
+pub fn save_png<P: AsRef<Path>>(
+    path: P,
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: usize,
+) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    write_png(out, framebuffer, palette, scale)
+}
+
+/// Records the display into an animated GIF or APNG file.
+///
+/// `push` is called once per emulated frame. Consecutive identical frames
+/// are merged into a single image shown for longer, so a mostly static game
+/// produces a small file. The file is written by `finish`.
+pub struct Recorder {
+    path: PathBuf,
+    format: Format,
+    palette: Palette,
+    scale: usize,
+    /// Distinct images with the time they appeared
+    frames: Vec<(Framebuffer, Duration)>,
+    end: Duration,
+}
+
+impl Recorder {
+    /// Creates a recorder for `path`, whose extension selects the format.
+    pub fn new<P: Into<PathBuf>>(path: P, palette: Palette, scale: usize) -> io::Result<Recorder> {
+        let path = path.into();
+        let format = Format::from_path(&path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: expected a .gif or .png file", path.display()),
+            )
+        })?;
+        Ok(Recorder {
+            path,
+            format,
+            palette,
+            scale,
+            frames: Vec::new(),
+            end: Duration::ZERO,
+        })
+    }
+
+    /// Adds the display as it looks at time `now` (from `Host::now`).
+    pub fn push(&mut self, framebuffer: &Framebuffer, now: Duration) {
+        self.end = now;
+        match self.frames.last() {
+            Some((last, _)) if last == framebuffer => {}
+            _ => self.frames.push((framebuffer.clone(), now)),
+        }
+    }
+
+    /// Number of distinct images recorded so far
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Writes the recording. The last image is shown for one frame past the
+    /// last `push`.
+    pub fn finish(self) -> io::Result<()> {
+        let out = BufWriter::new(File::create(&self.path)?);
+        match self.format {
+            Format::Gif => self.write_gif(out),
+            Format::Apng => self.write_apng(out),
+        }
+    }
+
+    /// How long every image is shown, in `1 / units_per_second` seconds.
+    ///
+    /// The running total is rounded rather than every single delay, so the
+    /// rounding error does not accumulate over the recording.
+    fn delays(&self, units_per_second: u128) -> Vec<u16> {
+        let frame = Duration::from_secs(1) / crate::scheduler::FRAME_RATE;
+        let start = self.frames.first().map_or(Duration::ZERO, |(_, at)| *at);
+        let ends = self
+            .frames
+            .iter()
+            .skip(1)
+            .map(|(_, at)| *at)
+            .chain(Some(self.end + frame));
+
+        let mut shown = 0;
+        ends.map(|end| {
+            let nanos = (end - start).as_nanos();
+            let until = (nanos * units_per_second + 500_000_000) / 1_000_000_000;
+            let delay = (until - shown).min(u16::MAX as u128);
+            shown += delay;
+            delay as u16
+        })
+        .collect()
+    }
+
+    fn write_gif<W: Write>(&self, out: W) -> io::Result<()> {
+        let Some((first, _)) = self.frames.first() else {
+            return Ok(());
+        };
+        let (width, height) = scaled_size(first, self.scale);
+        let palette: Vec<u8> = self
+            .palette
+            .colors
+            .iter()
+            .flat_map(|rgba| rgba[..3].to_vec())
+            .collect();
+
+        let mut encoder =
+            gif::Encoder::new(out, width as u16, height as u16, &palette).map_err(io_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io_error)?;
+
+        // GIF delays are in hundredths of a second
+        for ((framebuffer, _), delay) in self.frames.iter().zip(self.delays(100)) {
+            let mut frame = gif::Frame::from_indexed_pixels(
+                width as u16,
+                height as u16,
+                fitted_indexes(framebuffer, width, height),
+                None,
+            );
+            frame.delay = delay;
+            encoder.write_frame(&frame).map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    fn write_apng<W: Write>(&self, out: W) -> io::Result<()> {
+        let Some((first, _)) = self.frames.first() else {
+            return Ok(());
+        };
+        let (width, height) = scaled_size(first, self.scale);
+        let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(self.frames.len() as u32, 0)
+            .map_err(io_error)?;
+        let mut writer = encoder.write_header().map_err(io_error)?;
+
+        for ((framebuffer, _), delay) in self.frames.iter().zip(self.delays(1000)) {
+            writer.set_frame_delay(delay, 1000).map_err(io_error)?;
+            let rgba = to_rgba(&fitted_indexes(framebuffer, width, height), &self.palette);
+            writer.write_image_data(&rgba).map_err(io_error)?;
+        }
+        writer.finish().map_err(io_error)
+    }
+}
+
+fn io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::other(e)
+}
+
+fn scaled_size(framebuffer: &Framebuffer, scale: usize) -> (usize, usize) {
+    let (width, height) = framebuffer.resolution();
+    (width * scale, height * scale)
+}
+
+fn to_rgba(indexes: &[u8], palette: &Palette) -> Vec<u8> {
+    let colors: &[_; COLORS] = &palette.colors;
+    indexes
+        .iter()
+        .flat_map(|&index| colors[index as usize])
+        .collect()
+}
+
+/// Colour indexes of the framebuffer stretched to `width`x`height`.
+///
+/// An animation has a single size, so frames recorded after a resolution
+/// change are stretched to the size of the first one.
+fn fitted_indexes(framebuffer: &Framebuffer, width: usize, height: usize) -> Vec<u8> {
+    let (cols, rows) = framebuffer.resolution();
+    let scale_x = width / cols;
+    let scale_y = height / rows;
+    let mut indexes = Vec::with_capacity(width * height);
+    for y in 0..rows {
+        let row: Vec<u8> = (0..cols)
+            .flat_map(|x| std::iter::repeat_n(framebuffer.pixel(x, y), scale_x))
+            .collect();
+        for _ in 0..scale_y {
+            indexes.extend_from_slice(&row);
+        }
+    }
+    indexes
+}
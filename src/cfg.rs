@@ -0,0 +1,420 @@
+//! Static control-flow analysis of ROMs.
+//!
+//! [`Cfg::build`] disassembles a ROM by following the control flow from its
+//! start address, so that data between the code is left alone, and splits the
+//! code into basic blocks. Blocks are joined by the edges of JP, CALL and the
+//! skip instructions; a block ending in RET has no successors.
+//!
+//! Some things cannot be followed statically and are reported as warnings:
+//! indirect jumps (Bnnn), invalid instructions, and control flow leaving the
+//! ROM. Stores (Fx55 and Fx33) whose I is known from an earlier `LD I, nnn`
+//! in the same block are checked against the code found, to spot
+//! self-modifying code.
+//!
+//! The graph can be exported as Graphviz DOT (`dot -Tsvg`) or JSON.
+
+use crate::decoder::Instruction;
+use crate::memory;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// To the next instruction, including the return address after a CALL
+    /// and the instruction a skip may skip
+    Fallthrough,
+    Jump,
+    Call,
+    /// A skip instruction skipping: to the instruction after the next one
+    Skip,
+}
+
+impl EdgeKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    pub target: u16,
+}
+
+/// Instructions that run one after the other, entered at the first one only
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+    pub successors: Vec<Edge>,
+}
+
+impl Block {
+    /// Addresses of the instructions' bytes
+    pub fn range(&self) -> Range<u16> {
+        self.start..self.start + 2 * self.instructions.len() as u16
+    }
+
+    /// Whether the block ends the program's flow: RET, an indirect jump or an
+    /// invalid instruction
+    pub fn is_exit(&self) -> bool {
+        self.successors.is_empty()
+    }
+
+    /// Whether the last instruction is a skip
+    pub fn ends_with_skip(&self) -> bool {
+        self.successors
+            .iter()
+            .any(|edge| edge.kind == EdgeKind::Skip)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    /// Bnnn: the target depends on V0
+    IndirectJump { pc: u16 },
+    /// An opcode the interpreter does not implement, or 0nnn
+    InvalidInstruction { pc: u16, opcode: u16 },
+    /// A jump, call or fallthrough to an address outside the ROM
+    OutsideRom { pc: u16, target: u16 },
+    /// A store at `pc` overwrites the code at `address`
+    SelfModifying { pc: u16, address: u16 },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::IndirectJump { pc } => {
+                write!(f, "{:03X}: indirect jump, targets not followed", pc)
+            }
+            Warning::InvalidInstruction { pc, opcode } => {
+                write!(f, "{:03X}: invalid instruction {:04X}", pc, opcode)
+            }
+            Warning::OutsideRom { pc, target } => {
+                write!(
+                    f,
+                    "{:03X}: control flow leaves the ROM to {:03X}",
+                    pc, target
+                )
+            }
+            Warning::SelfModifying { pc, address } => {
+                write!(
+                    f,
+                    "{:03X}: store overwrites the code at {:03X}",
+                    pc, address
+                )
+            }
+        }
+    }
+}
+
+/// The control-flow graph of a ROM
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub start: u16,
+    /// By start address
+    pub blocks: BTreeMap<u16, Block>,
+    /// CALL targets
+    pub subroutines: BTreeSet<u16>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Cfg {
+    /// Analyses `rom`, loaded and entered at `start`
+    pub fn build(rom: &[u8], start: u16) -> Cfg {
+        let end = (start as usize + rom.len()).min(memory::SIZE) as u16;
+        let fetch = |pc: u16| -> Option<Instruction> {
+            if pc < start || pc + 1 >= end {
+                return None;
+            }
+            let offset = (pc - start) as usize;
+            Some(Instruction::decode(
+                (rom[offset] as u16) << 8 | rom[offset + 1] as u16,
+            ))
+        };
+
+        let mut warnings = Vec::new();
+        let mut subroutines = BTreeSet::new();
+        // Instructions reachable from the start, and where blocks begin
+        let mut code = BTreeMap::new();
+        let mut leaders = BTreeSet::from([start]);
+        let mut pending = vec![start];
+
+        while let Some(pc) = pending.pop() {
+            if code.contains_key(&pc) {
+                continue;
+            }
+            let instruction = match fetch(pc) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            code.insert(pc, instruction);
+
+            let edges = edges(pc, instruction);
+            if ends_block(instruction) {
+                leaders.extend(edges.iter().map(|edge| edge.target));
+            }
+            match instruction {
+                Instruction::Call(target) => {
+                    subroutines.insert(target);
+                }
+                Instruction::JpV0(_) => warnings.push(Warning::IndirectJump { pc }),
+                Instruction::Sys(_) | Instruction::Unknown(_) => {
+                    let offset = (pc - start) as usize;
+                    warnings.push(Warning::InvalidInstruction {
+                        pc,
+                        opcode: (rom[offset] as u16) << 8 | rom[offset + 1] as u16,
+                    });
+                }
+                _ => {}
+            }
+            for edge in edges {
+                if fetch(edge.target).is_some() {
+                    pending.push(edge.target);
+                } else {
+                    warnings.push(Warning::OutsideRom {
+                        pc,
+                        target: edge.target,
+                    });
+                }
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &leader in leaders.iter().filter(|pc| code.contains_key(pc)) {
+            let mut block = Block {
+                start: leader,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+            };
+            let mut pc = leader;
+            loop {
+                let instruction = code[&pc];
+                block.instructions.push((pc, instruction));
+                if ends_block(instruction) {
+                    block.successors = edges(pc, instruction);
+                    break;
+                }
+                pc += 2;
+                if leaders.contains(&pc) || !code.contains_key(&pc) {
+                    block.successors = vec![Edge {
+                        kind: EdgeKind::Fallthrough,
+                        target: pc,
+                    }];
+                    break;
+                }
+            }
+            blocks.insert(leader, block);
+        }
+
+        let mut cfg = Cfg {
+            start,
+            blocks,
+            subroutines,
+            warnings,
+        };
+        cfg.find_self_modifying_code();
+        cfg.warnings.sort_by_key(|warning| match *warning {
+            Warning::IndirectJump { pc }
+            | Warning::InvalidInstruction { pc, .. }
+            | Warning::OutsideRom { pc, .. }
+            | Warning::SelfModifying { pc, .. } => pc,
+        });
+        cfg.warnings.dedup();
+        cfg
+    }
+
+    /// The block containing the instruction at `pc`
+    pub fn block_at(&self, pc: u16) -> Option<&Block> {
+        self.blocks
+            .range(..=pc)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| block.range().contains(&pc))
+    }
+
+    /// Flags stores whose target, known from an `LD I, nnn` earlier in the
+    /// same block, overlaps an instruction
+    fn find_self_modifying_code(&mut self) {
+        let code: BTreeSet<u16> = self
+            .blocks
+            .values()
+            .flat_map(|block| block.range())
+            .collect();
+        for block in self.blocks.values() {
+            let mut i = None;
+            for &(pc, instruction) in &block.instructions {
+                let stored = match instruction {
+                    Instruction::LdI(nnn) => {
+                        i = Some(nnn);
+                        continue;
+                    }
+                    Instruction::AddI(_) => {
+                        i = None;
+                        continue;
+                    }
+                    Instruction::LdIVx(x) => x as u16 + 1,
+                    Instruction::LdB(_) => 3,
+                    _ => continue,
+                };
+                if let Some(i) = i {
+                    if let Some(&address) = code.range(i..i + stored).next() {
+                        self.warnings.push(Warning::SelfModifying { pc, address });
+                    }
+                }
+            }
+        }
+    }
+
+    /// The graph in Graphviz DOT: one node per block, listing its
+    /// instructions. Subroutine entries are drawn bold, calls dashed and
+    /// skips dotted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (pc, instruction) in &block.instructions {
+                write!(label, "{:03X}: {}\\l", pc, instruction).unwrap();
+            }
+            let style = if self.subroutines.contains(&block.start) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            writeln!(
+                dot,
+                "    b{:03X} [label=\"{}\"{}];",
+                block.start, label, style
+            )
+            .unwrap();
+        }
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                if !self.blocks.contains_key(&edge.target) {
+                    writeln!(
+                        dot,
+                        "    b{:03X} [label=\"{:03X}: outside the ROM\", style=dashed];",
+                        edge.target, edge.target
+                    )
+                    .unwrap();
+                }
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough | EdgeKind::Jump => "",
+                    EdgeKind::Call => " [style=dashed]",
+                    EdgeKind::Skip => " [style=dotted, label=skip]",
+                };
+                writeln!(
+                    dot,
+                    "    b{:03X} -> b{:03X}{};",
+                    block.start, edge.target, style
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The graph in JSON: addresses are numbers, instructions disassembled
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\n  \"start\": {},\n  \"blocks\": [\n", self.start);
+        for (index, block) in self.blocks.values().enumerate() {
+            let instructions: Vec<String> = block
+                .instructions
+                .iter()
+                .map(|(pc, instruction)| {
+                    format!(
+                        "{{\"address\": {}, \"instruction\": \"{}\"}}",
+                        pc, instruction
+                    )
+                })
+                .collect();
+            let successors: Vec<String> = block
+                .successors
+                .iter()
+                .map(|edge| {
+                    format!(
+                        "{{\"kind\": \"{}\", \"target\": {}}}",
+                        edge.kind.name(),
+                        edge.target
+                    )
+                })
+                .collect();
+            write!(
+                json,
+                "    {{\"start\": {}, \"instructions\": [{}], \"successors\": [{}]}}",
+                block.start,
+                instructions.join(", "),
+                successors.join(", ")
+            )
+            .unwrap();
+            json.push_str(if index + 1 < self.blocks.len() {
+                ",\n"
+            } else {
+                "\n"
+            });
+        }
+        let subroutines: Vec<String> = self.subroutines.iter().map(u16::to_string).collect();
+        let warnings: Vec<String> = self
+            .warnings
+            .iter()
+            .map(|warning| format!("\"{}\"", warning))
+            .collect();
+        write!(
+            json,
+            "  ],\n  \"subroutines\": [{}],\n  \"warnings\": [{}]\n}}\n",
+            subroutines.join(", "),
+            warnings.join(", ")
+        )
+        .unwrap();
+        json
+    }
+}
+
+/// Whether control may go anywhere else than the next instruction
+fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        Jp(_)
+            | Call(_)
+            | Ret
+            | JpV0(_)
+            | Sys(_)
+            | Unknown(_)
+            | SeByte(..)
+            | SneByte(..)
+            | SeReg(..)
+            | SneReg(..)
+            | Skp(_)
+            | Sknp(_)
+    )
+}
+
+/// Where control goes after the instruction at `pc`, as far as is known
+/// statically
+fn edges(pc: u16, instruction: Instruction) -> Vec<Edge> {
+    use Instruction::*;
+    let next = pc.wrapping_add(2);
+    let edge = |kind, target| Edge { kind, target };
+    match instruction {
+        Jp(target) => vec![edge(EdgeKind::Jump, target)],
+        Call(target) => vec![
+            edge(EdgeKind::Call, target),
+            edge(EdgeKind::Fallthrough, next),
+        ],
+        Ret | JpV0(_) | Sys(_) | Unknown(_) => Vec::new(),
+        SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_) => vec![
+            edge(EdgeKind::Fallthrough, next),
+            edge(EdgeKind::Skip, next.wrapping_add(2)),
+        ],
+        _ => vec![edge(EdgeKind::Fallthrough, next)],
+    }
+}
@@ -0,0 +1,423 @@
+//! Command line options of the SDL frontend.
+//!
+//! Display settings can also be given in a configuration file (`rchip8.ini` in
+//! the working directory, or the file passed with `--config`):
+//!
+//! ```text
+//! # Applies to every ROM
+//! palette = amber
+//!
+//! # Applies to ROMs whose file name is BRIX
+//! [BRIX]
+//! palette = lcd
+//! fg = #FF0000
+//! ```
+//!
+//! Options on the command line take precedence over the ROM's section, which
+//! takes precedence over the global settings. Quirks, speed, start address
+//! and colours not set anywhere come from the ROM database when the ROM is
+//! known.
+
+use r_chip_8::memory::Protection;
+use r_chip_8::palette::{self, Palette};
+use r_chip_8::romdb::{Database, RomMetadata};
+use r_chip_8::{memory, Quirks, Rgba};
+
+use crate::sdl_host::Sync;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+const DEFAULT_ROM_DIR: &str = "rom";
+const DEFAULT_IPF: u32 = 10;
+const DEFAULT_CONFIG_FILE: &str = "rchip8.ini";
+const DEFAULT_CAPTURE_SCALE: usize = 10;
+/// Deepest call stack `--stack-depth` accepts
+const MAX_STACK_DEPTH: usize = 64;
+
+const USAGE: &str = "\
+Usage: r_chip_8 [OPTIONS] [ROM]
+
+Without a ROM, a launcher lists the ROMs in the ROM directory. A ROM file
+dropped onto the window is loaded in its place.
+
+Options:
+    --ipf <N>    Instructions executed per 60 Hz frame (default: from the ROM
+                 database, else 10)
+    --vsync      Synchronise frames with the display refresh instead of a timer
+    --quirks <PROFILE>
+                 Interpreter quirks: vip, schip or modern (default: from the
+                 ROM database, else modern)
+    --stack-depth <N>
+                 Return addresses the stack holds, 1 to 64 (default: 12 for vip,
+                 else 16)
+    --start <ADDR>
+                 Hexadecimal load and start address: 200, or 600 for ETI-660
+                 programs (default: from the ROM database, else 200)
+    --protect <POLICY>
+                 Writes below 0x200: off, ignore or fault (default: off)
+    --palette <NAME>
+                 Colours: classic, amber, lcd, high-contrast or xo (default: classic)
+    --bg <RRGGBB>
+                 Background colour, overriding the palette
+    --fg <RRGGBB>
+                 Foreground colour, overriding the palette
+    --config <FILE>
+                 Configuration file (default: rchip8.ini, if present)
+    --rom-dir <DIR>
+                 Directory the launcher lists (default: rom)
+    --rom-db <DIR>
+                 Directory with the sha1-hashes.json and programs.json of the
+                 community CHIP-8 database (default: the built-in database)
+    --gdb <PORT> Wait for a GDB remote protocol client on localhost:PORT
+                 before starting, with the ROM halted
+    --screenshot <FILE>
+                 Save the display as PNG when quitting (F12 saves one any time)
+    --record <FILE>
+                 Record the session as an animated .gif or .png (APNG)
+    --record-audio <FILE>
+                 Record the buzzer as a 44.1 kHz .wav file
+    --capture-scale <N>
+                 Size of a display pixel in screenshots and recordings (default: 10)
+    --coverage <FILE>
+                 Write an annotated disassembly with instruction and skip counts
+                 to FILE, and an lcov tracefile to FILE.info, when quitting
+    --profile <FILE>
+                 Write a profile (hot spots, subroutines, delay timer waits) to
+                 FILE, and folded stacks for flamegraphs to FILE.folded, when
+                 quitting
+    -h, --help   Print this message";
+
+#[derive(Debug)]
+pub struct Config {
+    /// The ROM to run, or `None` for the launcher
+    pub rom: Option<String>,
+    pub rom_dir: String,
+    /// Instructions per frame
+    ipf: Option<u32>,
+    pub sync: Sync,
+    quirks: Option<Quirks>,
+    stack_depth: Option<usize>,
+    /// Load and start address
+    pub start: Option<u16>,
+    pub protection: Protection,
+    /// Display settings from the command line
+    display: Display,
+    /// Display settings from the configuration file: the global ones and
+    /// the ones of each ROM section
+    file_display: (Display, HashMap<String, Display>),
+    /// Directory of the ROM database
+    pub rom_db: Option<String>,
+    pub screenshot: Option<String>,
+    pub record: Option<String>,
+    pub record_audio: Option<String>,
+    pub capture_scale: usize,
+    /// Port of the GDB stub
+    pub gdb: Option<u16>,
+    /// Where to write the coverage listing
+    pub coverage: Option<String>,
+    /// Where to write the profile
+    pub profile: Option<String>,
+}
+
+/// Display settings that can come from the command line or the config file
+#[derive(Debug, Clone, Default)]
+struct Display {
+    palette: Option<Palette>,
+    bg: Option<Rgba>,
+    fg: Option<Rgba>,
+}
+
+impl Display {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "palette" => {
+                self.palette = Some(Palette::from_name(value).ok_or_else(|| {
+                    format!(
+                        "unknown palette: {} (expected one of {})",
+                        value,
+                        Palette::NAMES.join(", ")
+                    )
+                })?)
+            }
+            "bg" => self.bg = Some(parse_color(value)?),
+            "fg" => self.fg = Some(parse_color(value)?),
+            _ => return Err(format!("unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+
+    /// Fills the settings missing here from `other`
+    fn or(self, other: &Display) -> Display {
+        Display {
+            palette: self.palette.or(other.palette),
+            bg: self.bg.or(other.bg),
+            fg: self.fg.or(other.fg),
+        }
+    }
+
+    /// The palette, over `base` if none was selected
+    fn palette(&self, base: Option<Palette>) -> Palette {
+        let mut palette = self.palette.or(base).unwrap_or_default();
+        if let Some(bg) = self.bg {
+            palette.set_background(bg);
+        }
+        if let Some(fg) = self.fg {
+            palette.set_foreground(fg);
+        }
+        palette
+    }
+}
+
+impl Config {
+    /// Parses the process arguments, exiting with the usage message on error.
+    pub fn from_args() -> Config {
+        match Self::parse(env::args().skip(1)) {
+            Ok(config) => config,
+            Err(msg) => {
+                eprintln!("{}\n\n{}", msg, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
+        let mut config = Config {
+            rom: None,
+            rom_dir: DEFAULT_ROM_DIR.to_string(),
+            ipf: None,
+            sync: Sync::Timer,
+            quirks: None,
+            stack_depth: None,
+            start: None,
+            protection: Protection::default(),
+            display: Display::default(),
+            file_display: Default::default(),
+            rom_db: None,
+            screenshot: None,
+            record: None,
+            record_audio: None,
+            capture_scale: DEFAULT_CAPTURE_SCALE,
+            gdb: None,
+            coverage: None,
+            profile: None,
+        };
+        let mut config_file = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ipf" => {
+                    let value = args.next().ok_or("--ipf requires a value")?;
+                    config.ipf = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --ipf value: {}", value))?,
+                    );
+                }
+                "--vsync" => config.sync = Sync::Vsync,
+                "--quirks" => {
+                    let value = args.next().ok_or("--quirks requires a value")?;
+                    config.quirks = Some(Quirks::from_name(&value).ok_or_else(|| {
+                        format!(
+                            "unknown quirks profile: {} (expected one of {})",
+                            value,
+                            Quirks::NAMES.join(", ")
+                        )
+                    })?);
+                }
+                "--stack-depth" => {
+                    let value = args.next().ok_or("--stack-depth requires a value")?;
+                    config.stack_depth = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|depth| (1..=MAX_STACK_DEPTH).contains(depth))
+                            .ok_or_else(|| {
+                                format!(
+                                    "invalid --stack-depth value: {} (expected 1 to {})",
+                                    value, MAX_STACK_DEPTH
+                                )
+                            })?,
+                    );
+                }
+                "--start" => {
+                    let value = args.next().ok_or("--start requires a value")?;
+                    config.start = Some(
+                        parse_address(&value)
+                            .ok_or_else(|| format!("invalid --start address: {}", value))?,
+                    );
+                }
+                "--protect" => {
+                    let value = args.next().ok_or("--protect requires a value")?;
+                    config.protection = Protection::from_name(&value).ok_or_else(|| {
+                        format!(
+                            "unknown protection policy: {} (expected one of {})",
+                            value,
+                            Protection::NAMES.join(", ")
+                        )
+                    })?;
+                }
+                "--palette" | "--bg" | "--fg" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{} requires a value", arg))?;
+                    config.display.set(&arg[2..], &value)?;
+                }
+                "--screenshot" => {
+                    config.screenshot = Some(args.next().ok_or("--screenshot requires a value")?);
+                }
+                "--record" => {
+                    config.record = Some(args.next().ok_or("--record requires a value")?);
+                }
+                "--record-audio" => {
+                    config.record_audio =
+                        Some(args.next().ok_or("--record-audio requires a value")?);
+                }
+                "--capture-scale" => {
+                    let value = args.next().ok_or("--capture-scale requires a value")?;
+                    config.capture_scale = value
+                        .parse()
+                        .ok()
+                        .filter(|scale| *scale > 0)
+                        .ok_or_else(|| format!("invalid --capture-scale value: {}", value))?;
+                }
+                "--gdb" => {
+                    let value = args.next().ok_or("--gdb requires a value")?;
+                    config.gdb = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --gdb port: {}", value))?,
+                    );
+                }
+                "--rom-dir" => {
+                    config.rom_dir = args.next().ok_or("--rom-dir requires a value")?;
+                }
+                "--rom-db" => {
+                    config.rom_db = Some(args.next().ok_or("--rom-db requires a value")?);
+                }
+                "--coverage" => {
+                    config.coverage = Some(args.next().ok_or("--coverage requires a value")?);
+                }
+                "--profile" => {
+                    config.profile = Some(args.next().ok_or("--profile requires a value")?);
+                }
+                "--config" => {
+                    config_file = Some(args.next().ok_or("--config requires a value")?);
+                }
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+                _ => config.rom = Some(arg),
+            }
+        }
+
+        // Each of them takes over running the CPU
+        let debuggers = [
+            config.gdb.is_some(),
+            config.coverage.is_some(),
+            config.profile.is_some(),
+        ];
+        if debuggers.iter().filter(|set| **set).count() > 1 {
+            return Err("only one of --gdb, --coverage and --profile can be used".to_string());
+        }
+        config.file_display = match &config_file {
+            Some(path) => read_config_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                read_config_file(DEFAULT_CONFIG_FILE)?
+            }
+            None => Default::default(),
+        };
+
+        Ok(config)
+    }
+
+    /// The ROM database selected with `--rom-db`, or the built-in one
+    pub fn database(&self) -> Result<Database, String> {
+        match &self.rom_db {
+            Some(dir) => Database::load(dir).map_err(|e| format!("{}: {}", dir, e)),
+            None => Ok(Database::builtin()),
+        }
+    }
+
+    pub fn ipf(&self, known: Option<&RomMetadata>) -> u32 {
+        self.ipf
+            .or(known.and_then(|rom| rom.ipf))
+            .unwrap_or(DEFAULT_IPF)
+    }
+
+    pub fn quirks(&self, known: Option<&RomMetadata>) -> Quirks {
+        let mut quirks = self
+            .quirks
+            .or(known.and_then(|rom| rom.quirks))
+            .unwrap_or_default();
+        if let Some(depth) = self.stack_depth {
+            quirks.stack_depth = depth;
+        }
+        quirks
+    }
+
+    pub fn palette(&self, rom: &str, known: Option<&RomMetadata>) -> Palette {
+        let (global, sections) = &self.file_display;
+        let rom_name = Path::new(rom)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut display = self.display.clone();
+        if let Some(section) = sections.get(&rom_name) {
+            display = display.or(section);
+        }
+        display
+            .or(global)
+            .palette(known.and_then(|rom| rom.palette))
+    }
+}
+
+/// Parses a hexadecimal address inside memory, with or without `0x`
+fn parse_address(value: &str) -> Option<u16> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|address| (*address as usize) < memory::SIZE)
+}
+
+fn parse_color(value: &str) -> Result<Rgba, String> {
+    palette::parse_color(value).ok_or_else(|| format!("invalid colour: {}", value))
+}
+
+/// Reads the global settings and the settings of each section, named after
+/// the file name of a ROM.
+fn read_config_file(path: &str) -> Result<(Display, HashMap<String, Display>), String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut global = Display::default();
+    let mut sections = HashMap::new();
+    let mut section: Option<String> = None;
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.trim().to_string());
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}:{}: expected `key = value`", path, number + 1))?;
+        let target = match &section {
+            None => &mut global,
+            Some(name) => sections.entry(name.clone()).or_default(),
+        };
+        target
+            .set(key.trim(), value.trim())
+            .map_err(|e| format!("{}:{}: {}", path, number + 1, e))?;
+    }
+
+    Ok((global, sections))
+}
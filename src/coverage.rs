@@ -0,0 +1,256 @@
+//! Code coverage: which instructions a ROM executed, how often, and which way
+//! its skip instructions went.
+//!
+//! [`Coverage`] steps the CPU itself, so it is installed as the
+//! [`Debugger`] of a `Machine` (or its `run_frame` called in place of
+//! `Cpu::run_frame`). Afterwards [`Coverage::listing`] annotates the
+//! disassembly of the ROM with the counts, and [`Coverage::lcov`] writes the
+//! counts in the lcov tracefile format for `genhtml` and coverage tools,
+//! against the listing or against the source the ROM was assembled from.
+//!
+//! ```no_run
+//! use r_chip_8::coverage::Coverage;
+//! use r_chip_8::Cpu;
+//!
+//! let rom = std::fs::read("rom/test_opcode.ch8").unwrap();
+//! let mut cpu = Cpu::new();
+//! cpu.load_rom_bytes(&rom).unwrap();
+//! let mut coverage = Coverage::new();
+//! for _ in 0..600 {
+//!     coverage.run_frame(&mut cpu, 10);
+//! }
+//! print!("{}", coverage.listing(&rom, 0x200).text);
+//! ```
+
+use crate::cfg::Cfg;
+use crate::decoder::Instruction;
+use crate::host::Debugger;
+use crate::memory::SIZE;
+use crate::Cpu;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+
+/// Execution and skip counts of every address
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    hits: Vec<u32>,
+    /// For skip instructions: how often they skipped, and how often they did
+    /// not
+    skips: Vec<(u32, u32)>,
+}
+
+/// An annotated disassembly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub text: String,
+    /// The line of every instruction in `text`, counting from 1
+    pub lines: BTreeMap<u16, u32>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            hits: vec![0; SIZE],
+            skips: vec![(0, 0); SIZE],
+        }
+    }
+
+    /// How often the instruction at `address` was executed
+    pub fn hits(&self, address: u16) -> u32 {
+        self.hits[address as usize]
+    }
+
+    /// How often the skip instruction at `address` skipped and did not skip
+    pub fn skips(&self, address: u16) -> (u32, u32) {
+        self.skips[address as usize]
+    }
+
+    pub fn clear(&mut self) {
+        self.hits.fill(0);
+        self.skips.fill((0, 0));
+    }
+
+    /// Executes and counts a single instruction
+    pub fn step(&mut self, cpu: &mut Cpu) {
+        if cpu.fault().is_some() {
+            return;
+        }
+        let pc = cpu.pc();
+        let memory = cpu.memory();
+        let opcode =
+            (memory.read(pc as usize) as u16) << 8 | memory.read((pc as usize + 1) % SIZE) as u16;
+        cpu.step();
+
+        let hits = &mut self.hits[pc as usize];
+        *hits = hits.saturating_add(1);
+        if is_skip(Instruction::decode(opcode)) && cpu.fault().is_none() {
+            let (skipped, not_skipped) = &mut self.skips[pc as usize];
+            if cpu.pc() as usize == (pc as usize + 4) % SIZE {
+                *skipped = skipped.saturating_add(1);
+            } else {
+                *not_skipped = not_skipped.saturating_add(1);
+            }
+        }
+    }
+
+    /// Like `Cpu::run_frame`, counting every instruction
+    pub fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        for _ in 0..ipf {
+            self.step(cpu);
+            if cpu.in_vblank_wait() {
+                break;
+            }
+        }
+        cpu.end_frame();
+    }
+
+    /// Disassembles `rom`, loaded at `start`, with the count of every
+    /// instruction (`#####` if it never ran) and of both ways of every skip.
+    /// The instructions are the ones the static analysis finds (see `cfg`)
+    /// and the ones that ran.
+    pub fn listing(&self, rom: &[u8], start: u16) -> Listing {
+        let end = start as usize + rom.len();
+        let mut addresses: BTreeSet<u16> = Cfg::build(rom, start)
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter().map(|(pc, _)| *pc))
+            .collect();
+        addresses.extend(
+            (start as usize..end.saturating_sub(1))
+                .filter(|&address| self.hits[address] > 0)
+                .map(|address| address as u16),
+        );
+
+        let mut executed = 0;
+        let mut branches = 0;
+        let mut branches_taken = 0;
+        let mut body = String::new();
+        let mut lines = BTreeMap::new();
+        // The summary takes the first 3 lines
+        for (line, &address) in (4..).zip(&addresses) {
+            let offset = (address - start) as usize;
+            let opcode = (rom[offset] as u16) << 8 | rom[offset + 1] as u16;
+            let instruction = Instruction::decode(opcode);
+            let hits = self.hits(address);
+            let count = match hits {
+                0 => "#####".to_string(),
+                hits => hits.to_string(),
+            };
+            write!(
+                body,
+                "{:>9}  {:03X}:  {:04X}  {}",
+                count, address, opcode, instruction
+            )
+            .unwrap();
+            if is_skip(instruction) {
+                let (skipped, not_skipped) = self.skips(address);
+                write!(
+                    body,
+                    "{:width$}; skipped {}, not skipped {}",
+                    "",
+                    skipped,
+                    not_skipped,
+                    width = 20usize.saturating_sub(instruction.to_string().len())
+                )
+                .unwrap();
+                branches += 2;
+                branches_taken += (skipped > 0) as u32 + (not_skipped > 0) as u32;
+            }
+            body.push('\n');
+            executed += (hits > 0) as u32;
+            lines.insert(address, line);
+        }
+
+        let percent = |part: u32, whole: u32| match whole {
+            0 => 100.0,
+            whole => 100.0 * part as f64 / whole as f64,
+        };
+        let instructions = addresses.len() as u32;
+        let text = format!(
+            "; {} of {} instructions executed ({:.1}%)\n\
+             ; {} of {} skip branches taken ({:.1}%)\n\
+             ;    hits  addr  opcode instruction\n{}",
+            executed,
+            instructions,
+            percent(executed, instructions),
+            branches_taken,
+            branches,
+            percent(branches_taken, branches),
+            body
+        );
+        Listing { text, lines }
+    }
+
+    /// The counts in the lcov tracefile format, for the source file `source`
+    /// in which the instruction at an address is on line `lines[address]`:
+    /// the lines of a `Listing`, or of the source `rom` (loaded at `start`)
+    /// was assembled from. Skips are branches: branch 0 skips, branch 1 does
+    /// not.
+    pub fn lcov(&self, source: &str, lines: &BTreeMap<u16, u32>, rom: &[u8], start: u16) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", source);
+        let mut branches = 0;
+        let mut branches_hit = 0;
+        for (&address, &line) in lines {
+            let offset = address.wrapping_sub(start) as usize;
+            let instruction = match rom.get(offset..offset + 2) {
+                Some(&[high, low]) => Instruction::decode((high as u16) << 8 | low as u16),
+                _ => continue,
+            };
+            if !is_skip(instruction) {
+                continue;
+            }
+            let (skipped, not_skipped) = self.skips(address);
+            let count = |n: u32| match self.hits(address) {
+                0 => "-".to_string(),
+                _ => n.to_string(),
+            };
+            writeln!(lcov, "BRDA:{},0,0,{}", line, count(skipped)).unwrap();
+            writeln!(lcov, "BRDA:{},0,1,{}", line, count(not_skipped)).unwrap();
+            branches += 2;
+            branches_hit += (skipped > 0) as u32 + (not_skipped > 0) as u32;
+        }
+        writeln!(lcov, "BRF:{}\nBRH:{}", branches, branches_hit).unwrap();
+        for (&address, &line) in lines {
+            writeln!(lcov, "DA:{},{}", line, self.hits(address)).unwrap();
+        }
+        let hit = lines
+            .keys()
+            .filter(|&&address| self.hits(address) > 0)
+            .count();
+        writeln!(lcov, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit).unwrap();
+        lcov
+    }
+
+    /// Saves the listing of `rom`, loaded at `start`, to `path` and the lcov
+    /// tracefile of the listing to `path` with `.info` appended
+    pub fn save(&self, path: &str, rom: &[u8], start: u16) -> io::Result<()> {
+        let listing = self.listing(rom, start);
+        fs::write(path, &listing.text)?;
+        fs::write(
+            format!("{}.info", path),
+            self.lcov(path, &listing.lines, rom, start),
+        )
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+impl Debugger for Coverage {
+    fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        Coverage::run_frame(self, cpu, ipf);
+    }
+}
+
+fn is_skip(instruction: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_)
+    )
+}
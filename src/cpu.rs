@@ -1,22 +1,27 @@
-//! # r_chip_8
-//!
-//! `r_chip_8` is an interpreter for CHIP-8 programming language.
+//! The CHIP-8 CPU: registers, timers and the fetch/decode/execute cycle.
 
-use rand::Rng;
+use crate::decoder::Instruction;
+use crate::display::{Framebuffer, PLANES};
+use crate::host::KeyState;
+use crate::memory::{self, Access, MemoryHook, Protection, WriteFault};
+use crate::quirks::{Quirks, StackPolicy};
+use crate::rom::RomInfo;
+use crate::Memory;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::{BufReader, Read};
-use std::sync::atomic::{AtomicU8, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
-use std::{io, thread};
 
-mod memory;
-
-const START_SECTION: u16 = 0x200;
-const FONT_SECTION: u16 = 0x50;
+const START_SECTION: u16 = memory::PROGRAM.start;
+const FONT_SECTION: u16 = memory::FONT.start;
+/// Addresses are 12 bits: PC and the addresses computed from I wrap around
+const ADDRESS_MASK: u16 = memory::SIZE as u16 - 1;
 const WIDTH: usize = 64;
 const HEIGHT: usize = 32;
-const VRAM_SIZE: usize = WIDTH * HEIGHT;
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
 
 const FONTS: [u8; 5 * 16] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -37,93 +42,276 @@ const FONTS: [u8; 5 * 16] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// An error that stops the interpreter: once it occurred, `Cpu::step` does
+/// nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The instruction at `pc` wrote to protected memory. It stopped at
+    /// `address`: the bytes after it were not written.
+    ProtectedWrite { pc: u16, address: u16 },
+    /// CALL at `pc` with a full stack
+    StackOverflow { pc: u16 },
+    /// RET at `pc` with an empty stack
+    StackUnderflow { pc: u16 },
+    /// The instruction at `pc` is not one the interpreter implements
+    InvalidOpcode { pc: u16, opcode: u16 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::ProtectedWrite { pc, address } => {
+                write!(f, "{:03X}: {}", pc, WriteFault { address: *address })
+            }
+            Fault::StackOverflow { pc } => write!(f, "{:03X}: stack overflow", pc),
+            Fault::StackUnderflow { pc } => write!(f, "{:03X}: stack underflow", pc),
+            Fault::InvalidOpcode { pc, opcode } => {
+                write!(f, "{:03X}: invalid opcode {:04X}", pc, opcode)
+            }
+        }
+    }
+}
+
+/// Start of a save state, followed by the format version
+const STATE_MAGIC: &[u8; 4] = b"RC8S";
+const STATE_VERSION: u8 = 1;
+
+/// A subroutine call on the stack, see `Cpu::backtrace`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Where RET will continue
+    pub return_address: u16,
+    /// The CALL instruction, just before the return address
+    pub call_site: u16,
+    /// The subroutine called, if the call site still holds a CALL
+    pub target: Option<u16>,
+}
+
+/// The return address, annotated with the call site: `20A  (208: CALL 3A0)`
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}  ({:03X}: ", self.return_address, self.call_site)?;
+        match self.target {
+            Some(target) => write!(f, "CALL {:03X})", target),
+            None => write!(f, "?)"),
+        }
+    }
+}
+
+/// How `Cpu::step` executes instructions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Fetches and decodes every instruction it executes. This is the
+    /// reference implementation.
+    #[default]
+    Interpreter,
+    /// Decodes the instruction at an address once and keeps it until the
+    /// memory it was decoded from is written. Faster, and equivalent to the
+    /// interpreter, which it falls back to while a `MemoryHook` is installed.
+    Cached,
+}
+
 /// This is the structure of the chip-8 interpreter
 #[derive(Debug)]
 pub struct Cpu {
-    memory: memory::Memory,
+    memory: Memory,
+    /// Return addresses, `quirks.stack_depth` of them
     stack: Vec<u16>,
-    vram: Vec<u8>,
+    /// Number of return addresses on the stack, or with `StackPolicy::Wrap`
+    /// the index of the next free slot
+    sp: usize,
+    vram: Framebuffer,
+    /// SUPER-CHIP 128x64 mode
+    hires: bool,
     regs: Vec<u8>,
     keys: Vec<u8>,
     pc: u16,
     i: u16,
-    dt: Arc<AtomicU8>,
-    st: Arc<AtomicU8>,
-    draw: bool,
+    dt: u8,
+    st: u8,
     key_pressed: Option<u8>,
+    quirks: Quirks,
+    /// Set by DRW under the display wait quirk: the rest of the frame is
+    /// spent waiting for the vertical blank.
+    vblank_wait: bool,
+    /// Whether the sound timer was running when the timers last ticked
+    buzzer: bool,
+    /// Where ROMs are loaded and execution starts
+    start: u16,
+    fault: Option<Fault>,
+    rng: StdRng,
+    engine: Engine,
+    /// For `Engine::Cached`: the opcode at every address and its decoding,
+    /// once executed
+    decoded: Vec<Option<(u16, Instruction)>>,
 }
 
 impl Cpu {
     /// Construct a new instance of Chip8
     pub fn new() -> Cpu {
-        let mut memory = memory::Memory::new();
-        memory.write_vec(FONT_SECTION as usize, FONTS.to_vec());
-
-        let mut regs = Vec::new();
-        regs.resize(16, 0);
-
-        let mut vram = Vec::new();
-        vram.resize(VRAM_SIZE, 0);
+        let mut memory = Memory::new();
+        memory.load(FONT_SECTION as usize, &FONTS);
 
-        let mut keys = Vec::new();
-        keys.resize(16, 0);
+        let regs = vec![0; 16];
+        let vram = Framebuffer::new(WIDTH, HEIGHT);
+        let keys = vec![0; 16];
 
-        let stack = Vec::new();
+        let quirks = Quirks::default();
+        let stack = vec![0; quirks.stack_depth];
 
         Cpu {
             memory,
             stack,
+            sp: 0,
             vram,
+            hires: false,
             regs,
             keys,
             pc: START_SECTION,
             i: 0,
-            dt: Arc::new(AtomicU8::new(0)),
-            st: Arc::new(AtomicU8::new(0)),
-            draw: false,
+            dt: 0,
+            st: 0,
             key_pressed: None,
+            quirks,
+            vblank_wait: false,
+            buzzer: false,
+            start: START_SECTION,
+            fault: None,
+            rng: StdRng::from_entropy(),
+            engine: Engine::default(),
+            decoded: vec![None; memory::SIZE],
         }
     }
 
-    /// Load a rom file into `Memory`
-    pub fn load_rom(&mut self, file_path: &str) -> io::Result<()> {
+    /// Selects the quirks profile used from the next instruction on.
+    /// The stack is resized to the profile's depth, dropping the innermost
+    /// return addresses if it shrinks.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.stack.resize(quirks.stack_depth, 0);
+        self.sp = self.sp.min(quirks.stack_depth);
+    }
+
+    /// Sets what happens when a program writes below 0x200
+    pub fn set_protection(&mut self, protection: Protection) {
+        self.memory.set_protection(protection);
+    }
+
+    /// Sets where ROMs are loaded and execution starts: 0x200 (the default),
+    /// or 0x600 for ETI-660 programs. Call before `load_rom`. Fails if not
+    /// even one instruction fits between `start` and the end of memory.
+    pub fn set_start(&mut self, start: u16) -> io::Result<()> {
+        if start as usize + 2 > memory::SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("start address {:03X} is past the end of memory", start),
+            ));
+        }
+        self.start = start;
+        self.pc = start;
+        Ok(())
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Seeds the generator of RND, to make runs reproducible
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        // The interpreter does not keep the cache up to date
+        self.invalidate(0..memory::SIZE);
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Load a rom file into `Memory`, returning its size and hash
+    pub fn load_rom(&mut self, file_path: &str) -> io::Result<RomInfo> {
         let f = File::open(file_path)?;
         let mut reader = BufReader::new(f);
         let mut buffer = Vec::new();
 
         reader.read_to_end(&mut buffer)?;
 
-        self.memory.write_vec(START_SECTION as usize, buffer);
-
-        Ok(())
+        self.load_rom_bytes(&buffer)
     }
 
-    /// Starts the interpreter cycle
-    pub fn run(&mut self) {
-        // Fetch
-        let opcode = self.fetch();
-        // Decode & Execute
-        self.decode_and_execute(opcode);
+    /// Load a rom already in memory into `Memory`. Fails if it does not fit
+    /// between the start address and the end of memory.
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> io::Result<RomInfo> {
+        let room = memory::SIZE.saturating_sub(self.start as usize);
+        if rom.len() > room {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, but only {} bytes fit between {:03X} and {:03X}",
+                    rom.len(),
+                    room,
+                    self.start,
+                    memory::SIZE - 1
+                ),
+            ));
+        }
+        self.memory.load(self.start as usize, rom);
+        self.invalidate(0..memory::SIZE);
+        Ok(RomInfo::new(rom))
     }
 
-    pub fn start_timers(&self) {
-        let dt = Arc::clone(&self.dt);
-        let st = Arc::clone(&self.st);
+    /// Executes a single instruction, unless the interpreter faulted
+    pub fn step(&mut self) {
+        if self.fault.is_some() {
+            return;
+        }
+        match self.engine {
+            Engine::Interpreter => self.interpret(),
+            Engine::Cached => self.step_cached(),
+        }
+    }
 
-        thread::spawn(move || loop {
-            if dt.load(Ordering::SeqCst) > 0 {
-                dt.fetch_sub(1, Ordering::SeqCst);
+    /// Runs one 60 Hz frame: up to `ipf` instructions followed by a timer tick.
+    /// With the display wait quirk the frame ends early at the first DRW.
+    ///
+    /// The caller is responsible for calling this 60 times per second.
+    pub fn run_frame(&mut self, ipf: u32) {
+        for _ in 0..ipf {
+            self.step();
+            if self.vblank_wait {
+                break;
             }
-            if st.load(Ordering::SeqCst) > 0 {
-                st.fetch_sub(1, Ordering::SeqCst);
-            }
-            thread::sleep(Duration::from_micros(1000000 / 60));
-        });
+        }
+        self.end_frame();
+    }
+
+    /// Whether the rest of the frame is spent waiting for the vertical blank,
+    /// after a DRW under the display wait quirk. Callers that step through a
+    /// frame themselves stop stepping and call `end_frame`.
+    pub fn in_vblank_wait(&self) -> bool {
+        self.vblank_wait
+    }
+
+    /// Ends a frame stepped through with `step`: ticks the timers.
+    pub fn end_frame(&mut self) {
+        self.vblank_wait = false;
+        self.tick_timers();
+    }
+
+    /// Decrements the delay and sound timers, if they are non-zero.
+    /// Both timers count down at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.buzzer = self.st != 0;
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
     }
 
     /// Set 1 to `keys[key]` if the key `key` is pressed
     pub fn key_press(&mut self, key: u8) {
-        println!("Key Pressed: {}", key);
+        trace!("Key Pressed: {}", key);
         self.keys[key as usize] = 1;
         self.key_pressed = Some(key);
     }
@@ -131,23 +319,49 @@ impl Cpu {
     /// Set 0 to `keys[key]` if the key `key` is released
     pub fn key_release(&mut self, key: u8) {
         self.keys[key as usize] = 0;
-        println!("Key Released: {}", key);
+        trace!("Key Released: {}", key);
         self.key_pressed = None;
     }
 
+    /// Presses and releases keys so that exactly the keys in `keys` are down
+    pub fn set_keys(&mut self, keys: KeyState) {
+        for key in 0..16 {
+            let pressed = keys.is_pressed(key);
+            if pressed != (self.keys[key as usize] == 1) {
+                if pressed {
+                    self.key_press(key);
+                } else {
+                    self.key_release(key);
+                }
+            }
+        }
+    }
+
+    /// `step` for `Engine::Interpreter`. Kept out of line, like the slow paths
+    /// of `step_cached`, so that `step` saves few registers.
+    #[inline(never)]
+    fn interpret(&mut self) {
+        // Fetch
+        let opcode = self.fetch();
+        // Decode & Execute
+        self.decode_and_execute(opcode);
+    }
+
     /// Read the instruction that PC is currently pointing at from memory.
     /// An instruction is two bytes, so it will read two successive
     /// bytes from memory and combine them into one 16-bit instruction.
     fn fetch(&mut self) -> u16 {
-        let opcode_h = self.memory.read(self.pc as usize);
-        let opcode_l = self.memory.read((self.pc + 1) as usize);
+        let opcode_h = self.memory.read_as(Access::Execute, self.pc as usize);
+        let opcode_l = self
+            .memory
+            .read_as(Access::Execute, (self.pc as usize + 1) % memory::SIZE);
 
         let opcode = ((opcode_h as u16) << 8) | (opcode_l as u16);
 
-        println!("FETCH: {:#04X?} @ {:#04X?}", opcode, self.pc);
+        trace!("FETCH: {:#04X?} @ {:#04X?}", opcode, self.pc);
 
         // Incrementing PC
-        self.pc += 2;
+        self.pc = (self.pc + 2) & ADDRESS_MASK;
 
         opcode
     }
@@ -175,17 +389,28 @@ impl Cpu {
                         //
                         // The interpreter sets the program counter to the address
                         // at the top of the stack, then subtracts 1 from the stack pointer.
-                        let addr = self.stack.pop().unwrap();
-                        println!("RET to {:04X}", addr);
-                        self.pc = addr;
+                        if let Some(addr) = self.pop() {
+                            trace!("RET to {:04X}", addr);
+                            self.pc = addr;
+                        }
                     }
                     0x0E0 => {
                         // 00E0 - CLS
                         // Clear the display.
-                        println!("CLS");
+                        trace!("CLS");
                         self.vram.clear();
-                        self.vram.resize(WIDTH * HEIGHT, 0);
-                        self.draw = true;
+                    }
+                    0x0FE => {
+                        // 00FE - LOW (SUPER-CHIP)
+                        // Disable high resolution graphics mode.
+                        trace!("LOW");
+                        self.set_hires(false);
+                    }
+                    0x0FF => {
+                        // 00FF - HIGH (SUPER-CHIP)
+                        // Enable 128x64 high resolution graphics mode.
+                        trace!("HIGH");
+                        self.set_hires(true);
                     }
                     _ => {
                         // 0nnn - SYS addr
@@ -193,7 +418,7 @@ impl Cpu {
                         //
                         // This instruction is only used on the old computers on which
                         // Chip-8 was originally implemented. It is ignored by modern interpreters.
-                        panic!("Ignored by modern interpreter");
+                        trace!("SYS {:03X}", nnn);
                     }
                 }
             }
@@ -202,7 +427,7 @@ impl Cpu {
                 // Jump to location nnn.
                 //
                 // The interpreter sets the program counter to nnn.
-                println!("JP {:03X}", nnn);
+                trace!("JP {:03X}", nnn);
                 self.pc = nnn;
             }
             0x2 => {
@@ -212,9 +437,10 @@ impl Cpu {
                 // The interpreter increments the stack pointer,
                 // then puts the current PC on the top of the stack.
                 // The PC is then set to nnn.
-                println!("CALL {:03X}", nnn);
-                self.stack.push(self.pc);
-                self.pc = nnn;
+                trace!("CALL {:03X}", nnn);
+                if self.push(self.pc) {
+                    self.pc = nnn;
+                }
             }
             0x3 => {
                 // 3xkk - SE Vx, byte
@@ -222,9 +448,9 @@ impl Cpu {
                 //
                 // The interpreter compares register Vx to kk, and if they are equal,
                 // increments the program counter by 2.
-                println!("SE V{:1X}, {:02X}", x, kk);
+                trace!("SE V{:1X}, {:02X}", x, kk);
                 if self.regs[x] == kk {
-                    self.pc += 2;
+                    self.pc = (self.pc + 2) & ADDRESS_MASK;
                 }
             }
             0x4 => {
@@ -233,9 +459,9 @@ impl Cpu {
                 //
                 // The interpreter compares register Vx to kk, and if they are not equal,
                 // increments the program counter by 2.
-                println!("SNE V{:1X}, {:02X}", x, kk);
+                trace!("SNE V{:1X}, {:02X}", x, kk);
                 if self.regs[x] != kk {
-                    self.pc += 2;
+                    self.pc = (self.pc + 2) & ADDRESS_MASK;
                 }
             }
             0x5 => {
@@ -244,9 +470,9 @@ impl Cpu {
                 //
                 // The interpreter compares register Vx to register Vy, and if they are equal,
                 // increments the program counter by 2.
-                println!("SE V{:1X}, V{:1X}", x, y);
+                trace!("SE V{:1X}, V{:1X}", x, y);
                 if self.regs[x] == self.regs[y] {
-                    self.pc += 2;
+                    self.pc = (self.pc + 2) & ADDRESS_MASK;
                 }
             }
             0x6 => {
@@ -254,7 +480,7 @@ impl Cpu {
                 // Set Vx = kk.
                 //
                 // The interpreter puts the value kk into register Vx.
-                println!("LD V{:1X}, {:02X}", x, kk);
+                trace!("LD V{:1X}, {:02X}", x, kk);
                 self.regs[x] = kk;
             }
             0x7 => {
@@ -262,7 +488,7 @@ impl Cpu {
                 // Set Vx = Vx + kk.
                 //
                 // Adds the value kk to the value of register Vx, then stores the result in Vx.
-                println!("ADD V{:1X}, {:02X}", x, kk);
+                trace!("ADD V{:1X}, {:02X}", x, kk);
                 let mut vx = self.regs[x] as u16;
                 vx += kk as u16;
                 self.regs[x] = vx as u8;
@@ -274,7 +500,7 @@ impl Cpu {
                         // Set Vx = Vy.
                         //
                         // Stores the value of register Vy in register Vx.
-                        println!("LD V{:1X}, V{:1X}", x, y);
+                        trace!("LD V{:1X}, V{:1X}", x, y);
                         self.regs[x] = self.regs[y];
                     }
                     0x1 => {
@@ -285,7 +511,7 @@ impl Cpu {
                         // A bitwise OR compares the corresponding bits from two values,
                         // and if either bit is 1, then the same bit in the result is also 1.
                         // Otherwise, it is 0.
-                        println!("OR V{:1X}, V{:1X}", x, y);
+                        trace!("OR V{:1X}, V{:1X}", x, y);
                         self.regs[x] |= self.regs[y];
                     }
                     0x2 => {
@@ -296,7 +522,7 @@ impl Cpu {
                         // A bitwise AND compares the corresponding bits from two values,
                         // and if both bits are 1, then the same bit in the result is also 1.
                         // Otherwise, it is 0.
-                        println!("AND V{:1X}, V{:1X}", x, y);
+                        trace!("AND V{:1X}, V{:1X}", x, y);
                         self.regs[x] &= self.regs[y];
                     }
                     0x3 => {
@@ -307,7 +533,7 @@ impl Cpu {
                         // then stores the result in Vx. An exclusive OR compares the corresponding bits
                         // from two values, and if the bits are not both the same, then the corresponding
                         // bit in the result is set to 1. Otherwise, it is 0.
-                        println!("XOR V{:1X}, V{:1X}", x, y);
+                        trace!("XOR V{:1X}, V{:1X}", x, y);
                         self.regs[x] ^= self.regs[y];
                     }
                     0x4 => {
@@ -317,15 +543,12 @@ impl Cpu {
                         // The values of Vx and Vy are added together. If the result is greater than
                         // 8 bits (i.e., > 255,) VF is set to 1, otherwise 0. Only the lowest 8 bits of
                         // the result are kept, and stored in Vx.
-                        println!("ADD V{:1X}, V{:1X}", x, y);
-
-                        let temp: u16 = self.regs[x] as u16 + self.regs[y] as u16;
-
-                        self.regs[x] = temp as u8;
+                        trace!("ADD V{:1X}, V{:1X}", x, y);
 
-                        if temp > 0xFF {
-                            self.regs[0xF] = 1;
-                        }
+                        // VF is written last, so that it holds the flag when it is Vx
+                        let (sum, carry) = self.regs[x].overflowing_add(self.regs[y]);
+                        self.regs[x] = sum;
+                        self.regs[0xF] = carry as u8;
                     }
                     0x5 => {
                         // 8xy5 - SUB Vx, Vy
@@ -333,21 +556,13 @@ impl Cpu {
                         //
                         // If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx,
                         // and the results stored in Vx.
-                        println!("SUB V{:1X}, V{:1X}", x, y);
+                        //
+                        // (VF is also 1 when Vx = Vy, as nothing is borrowed.)
+                        trace!("SUB V{:1X}, V{:1X}", x, y);
 
-                        if self.regs[x] >= self.regs[y] {
-                            if self.regs[x] > self.regs[y] {
-                                self.regs[0xF] = 1;
-                            } else {
-                                self.regs[0xF] = 0;
-                            }
-                            self.regs[x] -= self.regs[y];
-                        } else {
-                            self.regs[0xF] = 0;
-                            let temp_y = 0xFF - self.regs[y];
-                            println!("SUB {}, {}, {}", self.regs[x], self.regs[y], temp_y);
-                            self.regs[x] += temp_y + 1;
-                        }
+                        let (difference, borrow) = self.regs[x].overflowing_sub(self.regs[y]);
+                        self.regs[x] = difference;
+                        self.regs[0xF] = !borrow as u8;
                     }
                     0x6 => {
                         // 8xy6 - SHR Vx {, Vy}
@@ -355,10 +570,11 @@ impl Cpu {
                         //
                         // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
                         // Then Vx is divided by 2.
-                        println!("SHR V{:1X}", x);
+                        trace!("SHR V{:1X}", x);
 
-                        self.regs[0xF] = self.regs[x] & 0b1;
-                        self.regs[x] >>= 1;
+                        let vx = self.regs[x];
+                        self.regs[x] = vx >> 1;
+                        self.regs[0xF] = vx & 0b1;
                     }
                     0x7 => {
                         // 8xy7 - SUBN Vx, Vy
@@ -366,21 +582,13 @@ impl Cpu {
                         //
                         // If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy,
                         // and the results stored in Vx.
-                        println!("SUBN V{:1X}, V{:1X}", x, y);
+                        //
+                        // (VF is also 1 when Vx = Vy, as nothing is borrowed.)
+                        trace!("SUBN V{:1X}, V{:1X}", x, y);
 
-                        if self.regs[y] >= self.regs[x] {
-                            if self.regs[y] > self.regs[x] {
-                                self.regs[0xF] = 1;
-                            } else {
-                                self.regs[0xF] = 0;
-                            }
-                            self.regs[x] = self.regs[y] - self.regs[x];
-                        } else {
-                            self.regs[0xF] = 0;
-                            let temp_x = 0xFF - self.regs[x];
-                            println!("SUB {}, {}, {}", self.regs[x], self.regs[y], temp_x);
-                            self.regs[x] = self.regs[y] + temp_x + 1;
-                        }
+                        let (difference, borrow) = self.regs[y].overflowing_sub(self.regs[x]);
+                        self.regs[x] = difference;
+                        self.regs[0xF] = !borrow as u8;
                     }
                     0xE => {
                         // 8xyE - SHL Vx {, Vy}
@@ -388,11 +596,12 @@ impl Cpu {
                         //
                         // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0.
                         // Then Vx is multiplied by 2.
-                        println!("SHL V{:1X}", x);
-                        self.regs[0xF] = (self.regs[x] >> 7) & 0b1;
-                        self.regs[x] <<= 1;
+                        trace!("SHL V{:1X}", x);
+                        let vx = self.regs[x];
+                        self.regs[x] = vx << 1;
+                        self.regs[0xF] = vx >> 7;
                     }
-                    _ => panic!("Not valid!"),
+                    _ => self.invalid_opcode(opcode),
                 }
             }
             0x9 => {
@@ -401,9 +610,9 @@ impl Cpu {
                 //
                 // The values of Vx and Vy are compared, and if they are not equal,
                 // the program counter is increased by 2.
-                println!("SNE V{:1X}, V{:1X}", x, y);
+                trace!("SNE V{:1X}, V{:1X}", x, y);
                 if self.regs[x] != self.regs[y] {
-                    self.pc += 2;
+                    self.pc = (self.pc + 2) & ADDRESS_MASK;
                 }
             }
             0xA => {
@@ -411,7 +620,7 @@ impl Cpu {
                 // Set I = nnn.
                 //
                 // The value of register I is set to nnn.
-                println!("LD I, {:3X}", nnn);
+                trace!("LD I, {:3X}", nnn);
                 self.i = nnn;
             }
             0xB => {
@@ -419,8 +628,8 @@ impl Cpu {
                 // Jump to location nnn + V0.
                 //
                 // The program counter is set to nnn plus the value of V0.
-                println!("JP V0, {:3X}", nnn);
-                self.pc = nnn + self.regs[0] as u16;
+                trace!("JP V0, {:3X}", nnn);
+                self.pc = (nnn + self.regs[0] as u16) & ADDRESS_MASK;
             }
             0xC => {
                 // Cxkk - RND Vx, byte
@@ -429,11 +638,10 @@ impl Cpu {
                 // The interpreter generates a random number from 0 to 255,
                 // which is then ANDed with the value kk. The results are stored in Vx.
                 // See instruction 8xy2 for more information on AND.
-                println!("RND V{:1X}, {:3X}", x, nnn);
-                let mut rng = rand::thread_rng();
-                let rnd_num = rng.gen_range(0..255);
+                trace!("RND V{:1X}, {:3X}", x, nnn);
+                let rnd_num: u8 = self.rng.gen();
                 self.regs[x] = rnd_num & kk;
-                println!("RND {}", self.regs[x]);
+                trace!("RND {}", self.regs[x]);
             }
             0xD => {
                 // Dxyn - DRW Vx, Vy, nibble
@@ -446,32 +654,15 @@ impl Cpu {
                 // so part of it is outside the coordinates of the display, it wraps around to the
                 // opposite side of the screen. See instruction 8xy3 for more information on XOR,
                 // and section 2.4, Display, for more information on the Chip-8 screen and sprites.
-                println!("DRW V{:1X}, V{:1X}, {:01X}", x, y, n);
-
-                let x_coor = self.regs[x] % 64; // col
-                let y_coor = self.regs[y] % 32; // row
-
-                self.regs[0xF] = 0;
-
-                let data = self.memory.read_chunk(self.i as usize, n as usize);
-
-                for (idx, byte) in data.iter().enumerate() {
-                    for bit_idx in 0..8 {
-                        // Get sprite pixel value
-                        let bit = (byte & (0x80 >> bit_idx)) >> (7 - bit_idx);
-                        if bit == 1 {
-                            // Calculate vram idx
-                            let mut vram_idx =
-                                (y_coor as usize + idx) * WIDTH + x_coor as usize + bit_idx;
-                            vram_idx %= WIDTH * HEIGHT;
-                            if self.vram[vram_idx] == 1 {
-                                self.regs[0xF] = 1;
-                            }
-                            self.vram[vram_idx] ^= bit;
-                        }
-                    }
-                }
-                self.draw = true;
+                //
+                // Only the starting position wraps unconditionally; whether the parts of the
+                // sprite past the right and bottom edges wrap around or are clipped depends on
+                // `Quirks::wrap_sprites`. In SUPER-CHIP, Dxy0 draws a 16x16 sprite in high
+                // resolution mode (8x16 in low resolution).
+                trace!("DRW V{:1X}, V{:1X}, {:01X}", x, y, n);
+
+                self.regs[0xF] = self.draw_sprite(self.regs[x], self.regs[y], n);
+                self.vblank_wait = self.quirks.display_wait;
             }
             0xE => {
                 match kk {
@@ -481,9 +672,9 @@ impl Cpu {
                         //
                         // Checks the keyboard, and if the key corresponding to the value of Vx is
                         // currently in the down position, PC is increased by 2.
-                        println!("SKP V{:1X}", x);
-                        if self.keys[self.regs[x] as usize] == 1 {
-                            self.pc += 2;
+                        trace!("SKP V{:1X}", x);
+                        if self.key_down(x) {
+                            self.pc = (self.pc + 2) & ADDRESS_MASK;
                         }
                     }
                     0xA1 => {
@@ -492,12 +683,12 @@ impl Cpu {
                         //
                         // Checks the keyboard, and if the key corresponding to the value of Vx is
                         // currently in the up position, PC is increased by 2.
-                        println!("SKNP V{:1X}", x);
-                        if self.keys[self.regs[x] as usize] == 0 {
-                            self.pc += 2;
+                        trace!("SKNP V{:1X}", x);
+                        if !self.key_down(x) {
+                            self.pc = (self.pc + 2) & ADDRESS_MASK;
                         }
                     }
-                    _ => panic!("Error on OPCODE!"),
+                    _ => self.invalid_opcode(opcode),
                 }
             }
             0xF => {
@@ -507,8 +698,8 @@ impl Cpu {
                         // Set Vx = delay timer value.
                         //
                         // The value of DT is placed into Vx.
-                        println!("LD V{:01X}, DT", x);
-                        self.regs[x] = self.dt.load(Ordering::SeqCst);
+                        trace!("LD V{:01X}, DT", x);
+                        self.regs[x] = self.dt;
                     }
                     0x0A => {
                         // Fx0A - LD Vx, K
@@ -516,11 +707,11 @@ impl Cpu {
                         //
                         // All execution stops until a key is pressed, then the value of that key
                         // is stored in Vx.
-                        println!("LD V{:01X}, K", x);
+                        trace!("LD V{:01X}, K", x);
                         if let Some(key) = self.key_pressed {
                             self.regs[x] = key;
                         } else {
-                            self.pc -= 2;
+                            self.pc = self.pc.wrapping_sub(2) & ADDRESS_MASK;
                         }
                     }
                     0x15 => {
@@ -528,24 +719,24 @@ impl Cpu {
                         // Set delay timer = Vx.
                         //
                         // DT is set equal to the value of Vx.
-                        println!("LD DT, V{:01X}", x);
-                        self.dt.store(self.regs[x], Ordering::SeqCst);
+                        trace!("LD DT, V{:01X}", x);
+                        self.dt = self.regs[x];
                     }
                     0x18 => {
                         // Fx18 - LD ST, Vx
                         // Set sound timer = Vx.
                         //
                         // ST is set equal to the value of Vx.
-                        println!("LD ST, V{:01X}", x);
-                        self.st.store(self.regs[x], Ordering::SeqCst);
+                        trace!("LD ST, V{:01X}", x);
+                        self.st = self.regs[x];
                     }
                     0x1E => {
                         // Fx1E - ADD I, Vx
                         // Set I = I + Vx.
                         //
                         // The values of I and Vx are added, and the results are stored in I.
-                        println!("ADD I, V{:1X}", x);
-                        self.i += self.regs[x] as u16;
+                        trace!("ADD I, V{:1X}", x);
+                        self.i = self.i.wrapping_add(self.regs[x] as u16);
                     }
                     0x29 => {
                         // Fx29 - LD F, Vx
@@ -554,7 +745,7 @@ impl Cpu {
                         // The value of I is set to the location for the hexadecimal sprite corresponding
                         // to the value of Vx. See section 2.4, Display, for more information on the Chip-8
                         // hexadecimal font.
-                        println!("LD F, V{:01X}", x);
+                        trace!("LD F, V{:01X}", x);
                         self.i = FONT_SECTION + self.regs[x] as u16 * 5;
                     }
                     0x33 => {
@@ -564,7 +755,7 @@ impl Cpu {
                         // The interpreter takes the decimal value of Vx, and places the hundreds digit
                         // in memory at location in I, the tens digit at location I+1, and the ones digit
                         // at location I+2.
-                        println!("LD B, V{:1X}", x);
+                        trace!("LD B, V{:1X}", x);
 
                         let mut vx = self.regs[x];
                         let hundreds: u8 = vx / 100;
@@ -572,8 +763,11 @@ impl Cpu {
                         let tens: u8 = vx / 10;
                         vx -= tens * 10;
                         let ones: u8 = vx;
-                        self.memory
-                            .write_vec(self.i as usize, vec![hundreds, tens, ones]);
+                        for (offset, digit) in [hundreds, tens, ones].into_iter().enumerate() {
+                            if !self.store(self.address(offset), digit) {
+                                break;
+                            }
+                        }
                     }
                     0x55 => {
                         // Fx55 - LD [I], Vx
@@ -581,11 +775,12 @@ impl Cpu {
                         //
                         // The interpreter copies the values of registers V0 through Vx into memory,
                         // starting at the address in I.
-                        println!("LD [I], V{:1X}", x);
+                        trace!("LD [I], V{:1X}", x);
 
                         for reg in 0..x + 1 {
-                            self.memory
-                                .write((self.i + reg as u16) as usize, self.regs[reg]);
+                            if !self.store(self.address(reg), self.regs[reg]) {
+                                break;
+                            }
                         }
                     }
                     0x65 => {
@@ -594,16 +789,198 @@ impl Cpu {
                         //
                         // The interpreter reads values from memory starting at location I into
                         // registers V0 through Vx.
-                        println!("LD V{:1X}, [I]", x);
+                        trace!("LD V{:1X}, [I]", x);
 
                         for reg in 0..x + 1 {
-                            self.regs[reg] = self.memory.read((self.i + reg as u16) as usize);
+                            self.regs[reg] = self.memory.read_as(Access::Read, self.address(reg));
                         }
                     }
-                    _ => panic!("Error on OPCODE!"),
+                    _ => self.invalid_opcode(opcode),
                 }
             }
-            _ => panic!("opcode not valid!"),
+            _ => unreachable!("opcode type is a nibble"),
+        }
+    }
+
+    /// `step` for `Engine::Cached`. Control flow, loads, key waits and the
+    /// logical operations run from the decoded instruction; the rest, which writes
+    /// memory, sets VF or is rare, goes through `decode_and_execute` so that
+    /// it is implemented once.
+    fn step_cached(&mut self) {
+        let pc = self.pc as usize;
+        // The hook must see the fetches, and an instruction at the last byte
+        // of memory wraps around, which the cache does not handle
+        if self.memory.has_hook() || pc + 1 >= memory::SIZE {
+            self.interpret();
+            return;
+        }
+        let (opcode, instruction) = match self.decoded[pc] {
+            Some(decoded) => decoded,
+            None => self.decode_at(pc),
+        };
+        trace!("FETCH: {:#04X?} @ {:#04X?}", opcode, self.pc);
+        self.pc = (self.pc + 2) & ADDRESS_MASK;
+
+        use Instruction::*;
+        match instruction {
+            Ret => {
+                if let Some(addr) = self.pop() {
+                    self.pc = addr;
+                }
+            }
+            Jp(nnn) => self.pc = nnn,
+            Call(nnn) => {
+                if self.push(self.pc) {
+                    self.pc = nnn;
+                }
+            }
+            SeByte(x, kk) => self.skip_if(self.regs[x as usize] == kk),
+            SneByte(x, kk) => self.skip_if(self.regs[x as usize] != kk),
+            SeReg(x, y) => self.skip_if(self.regs[x as usize] == self.regs[y as usize]),
+            SneReg(x, y) => self.skip_if(self.regs[x as usize] != self.regs[y as usize]),
+            LdByte(x, kk) => self.regs[x as usize] = kk,
+            AddByte(x, kk) => self.regs[x as usize] = self.regs[x as usize].wrapping_add(kk),
+            LdReg(x, y) => self.regs[x as usize] = self.regs[y as usize],
+            Or(x, y) => self.regs[x as usize] |= self.regs[y as usize],
+            And(x, y) => self.regs[x as usize] &= self.regs[y as usize],
+            Xor(x, y) => self.regs[x as usize] ^= self.regs[y as usize],
+            LdI(nnn) => self.i = nnn,
+            JpV0(nnn) => self.pc = (nnn + self.regs[0] as u16) & ADDRESS_MASK,
+            Skp(x) => self.skip_if(self.key_down(x as usize)),
+            Sknp(x) => self.skip_if(!self.key_down(x as usize)),
+            LdVxDt(x) => self.regs[x as usize] = self.dt,
+            LdVxK(x) => match self.key_pressed {
+                Some(key) => self.regs[x as usize] = key,
+                None => self.pc = self.pc.wrapping_sub(2) & ADDRESS_MASK,
+            },
+            LdDtVx(x) => self.dt = self.regs[x as usize],
+            LdStVx(x) => self.st = self.regs[x as usize],
+            AddI(x) => self.i = self.i.wrapping_add(self.regs[x as usize] as u16),
+            LdF(x) => self.i = FONT_SECTION + self.regs[x as usize] as u16 * 5,
+            _ => self.decode_and_execute(opcode),
+        }
+    }
+
+    /// Decodes the instruction at `pc` into the cache
+    #[cold]
+    fn decode_at(&mut self, pc: usize) -> (u16, Instruction) {
+        let opcode = (self.memory.read(pc) as u16) << 8 | self.memory.read(pc + 1) as u16;
+        let decoded = (opcode, Instruction::decode(opcode));
+        self.decoded[pc] = Some(decoded);
+        decoded
+    }
+
+    /// Writes `value` for Fx33 or Fx55, dropping the decoded instructions
+    /// it overwrites. Returns false if the write faulted, which ends the
+    /// instruction there.
+    fn store(&mut self, address: usize, value: u8) -> bool {
+        let written = self.memory.write(address, value);
+        self.check_write(written);
+        // The instruction before the byte ends in it
+        self.decoded[(address + memory::SIZE - 1) % memory::SIZE] = None;
+        self.decoded[address] = None;
+        written.is_ok()
+    }
+
+    /// The address `offset` bytes after I, wrapping around memory
+    fn address(&self, offset: usize) -> usize {
+        (self.i as usize + offset) % memory::SIZE
+    }
+
+    /// Whether the key in the low nibble of Vx is down
+    fn key_down(&self, x: usize) -> bool {
+        self.keys[(self.regs[x] & 0xF) as usize] == 1
+    }
+
+    /// The address of the instruction being executed, PC having moved past it
+    fn instruction_address(&self) -> u16 {
+        self.pc.wrapping_sub(2) & ADDRESS_MASK
+    }
+
+    /// Stops the interpreter at an opcode it does not implement
+    fn invalid_opcode(&mut self, opcode: u16) {
+        trace!("FAULT: invalid opcode {:04X}", opcode);
+        self.fault = Some(Fault::InvalidOpcode {
+            pc: self.instruction_address(),
+            opcode,
+        });
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc = (self.pc + 2) & ADDRESS_MASK;
+        }
+    }
+
+    /// Drops the decoded instructions at `addresses`
+    fn invalidate(&mut self, addresses: std::ops::Range<usize>) {
+        let end = addresses.end.min(memory::SIZE);
+        if addresses.start < end {
+            self.decoded[addresses.start..end].fill(None);
+        }
+    }
+
+    /// XORs the sprite at I onto the display at (`x`, `y`) and returns the value
+    /// for VF.
+    ///
+    /// VF is normally 1 if any pixel was erased. With `Quirks::collision_rows` in
+    /// high resolution mode it is instead the number of sprite rows that collided
+    /// plus the number of rows clipped off the bottom edge, as on SUPER-CHIP 1.1.
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u8) -> u8 {
+        let (width, height) = self.vram.resolution();
+        let (sprite_width, rows) = match n {
+            0 if self.hires => (16, 16),
+            0 => (8, 16),
+            n => (8, n as usize),
+        };
+        let bytes_per_row = sprite_width / 8;
+        let wrap = self.quirks.wrap_sprites;
+
+        let x0 = x as usize % width;
+        let y0 = y as usize % height;
+
+        let len = rows * bytes_per_row;
+        let mut sprite = [0; 32];
+        let data = &mut sprite[..len];
+        if self.address(0) + len <= memory::SIZE {
+            data.copy_from_slice(
+                self.memory
+                    .read_chunk_as(Access::Read, self.address(0), len),
+            );
+        } else {
+            for (offset, byte) in data.iter_mut().enumerate() {
+                *byte = self.memory.read_as(Access::Read, self.address(offset));
+            }
+        }
+
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+
+        for (row, bytes) in data.chunks(bytes_per_row).enumerate() {
+            let mut py = y0 + row;
+            if py >= height {
+                if !wrap {
+                    clipped_rows += 1;
+                    continue;
+                }
+                py %= height;
+            }
+
+            // Left-align the row in 16 bits so both sprite widths are handled alike
+            let bits = bytes.iter().enumerate().fold(0u16, |acc, (idx, byte)| {
+                acc | (*byte as u16) << (8 - 8 * idx)
+            });
+
+            let mask = self.vram.place(bits, x0, wrap);
+            if self.vram.xor_row(0, py, mask) {
+                collided_rows += 1;
+            }
+        }
+
+        if self.hires && self.quirks.collision_rows {
+            collided_rows + clipped_rows
+        } else {
+            (collided_rows > 0) as u8
         }
     }
 
@@ -621,15 +998,429 @@ impl Cpu {
         (nnn, n, x, y, kk)
     }
 
-    pub fn vram(&self, x: usize, y: usize) -> u8 {
-        self.vram[y * WIDTH + x]
+    /// Pushes a return address. Returns false if the CALL must not happen
+    /// because the interpreter faulted.
+    fn push(&mut self, addr: u16) -> bool {
+        let depth = self.stack.len();
+        if self.sp >= depth {
+            match self.quirks.stack_policy {
+                StackPolicy::Error => {
+                    self.fault = Some(Fault::StackOverflow {
+                        pc: self.instruction_address(),
+                    });
+                    return false;
+                }
+                StackPolicy::Wrap => self.sp = 0,
+                StackPolicy::Ignore => return true,
+            }
+        }
+        if depth > 0 {
+            self.stack[self.sp] = addr;
+            self.sp += 1;
+        }
+        true
+    }
+
+    /// Pops a return address, or returns `None` if RET does nothing
+    fn pop(&mut self) -> Option<u16> {
+        if self.sp == 0 {
+            match self.quirks.stack_policy {
+                StackPolicy::Error => {
+                    self.fault = Some(Fault::StackUnderflow {
+                        pc: self.instruction_address(),
+                    });
+                    return None;
+                }
+                StackPolicy::Wrap if !self.stack.is_empty() => self.sp = self.stack.len(),
+                _ => return None,
+            }
+        }
+        self.sp -= 1;
+        Some(self.stack[self.sp])
+    }
+
+    /// Records a protected write as the fault of the current instruction
+    fn check_write(&mut self, written: Result<(), WriteFault>) {
+        if let (Err(WriteFault { address }), None) = (written, self.fault) {
+            trace!("FAULT: write to {:03X}", address);
+            self.fault = Some(Fault::ProtectedWrite {
+                pc: self.instruction_address(),
+                address,
+            });
+        }
+    }
+
+    /// Switches between the 64x32 and the 128x64 display, clearing it
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        if hires {
+            self.vram.resize(HIRES_WIDTH, HIRES_HEIGHT);
+        } else {
+            self.vram.resize(WIDTH, HEIGHT);
+        }
+    }
+
+    /// Program counter
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc & ADDRESS_MASK;
+    }
+
+    /// Index register
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    /// General purpose registers V0 to VF
+    pub fn registers(&self) -> &[u8] {
+        &self.regs
+    }
+
+    /// Sets Vx
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.regs[x] = value;
+    }
+
+    /// Return addresses, innermost call last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    /// Maximum number of return addresses on the stack
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// The calls on the stack, innermost first
+    pub fn backtrace(&self) -> Vec<StackFrame> {
+        self.stack()
+            .iter()
+            .rev()
+            .map(|&return_address| {
+                let call_site = return_address.wrapping_sub(2);
+                let opcode = (call_site as usize + 1 < memory::SIZE).then(|| {
+                    (self.memory.read(call_site as usize) as u16) << 8
+                        | self.memory.read(call_site as usize + 1) as u16
+                });
+                let target = match opcode.map(Instruction::decode) {
+                    Some(Instruction::Call(nnn)) => Some(nnn),
+                    _ => None,
+                };
+                StackFrame {
+                    return_address,
+                    call_site,
+                    target,
+                }
+            })
+            .collect()
+    }
+
+    /// Delay timer
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn set_dt(&mut self, dt: u8) {
+        self.dt = dt;
+    }
+
+    /// Sound timer
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    pub fn set_st(&mut self, st: u8) {
+        self.st = st;
+    }
+
+    /// The error that stopped the interpreter, if any
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Memory as a debugger sees it: to install hooks or patch bytes
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        // Anything may be patched
+        self.invalidate(0..memory::SIZE);
+        &mut self.memory
     }
 
-    pub fn draw(&mut self) -> bool {
-        self.draw
+    /// The first installed memory hook that is a `T`. Unlike going through
+    /// `memory_mut`, this keeps the instructions `Engine::Cached` decoded, so
+    /// it is cheap enough to call after every step.
+    pub fn hook_mut<T: MemoryHook>(&mut self) -> Option<&mut T> {
+        self.memory.hook_mut()
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.vram
+    }
+
+    /// Returns whether the display changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        self.vram.take_dirty()
     }
 
     pub fn play(&self) -> bool {
-        self.st.load(Ordering::SeqCst) != 0
+        self.st != 0
+    }
+
+    /// Whether the buzzer sounded during the last frame. Unlike `play`, this
+    /// includes a sound timer that ran out at the end of the frame.
+    pub fn buzzer(&self) -> bool {
+        self.buzzer
+    }
+}
+
+impl Cpu {
+    /// Saves everything that `step` reads or writes: memory, registers,
+    /// stack, timers, keys, display and the quirks profile, in a versioned
+    /// binary format. The memory protection, the engine and the state of the
+    /// RND generator are settings of the embedder and are not saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(memory::SIZE + 2048);
+        state.extend_from_slice(STATE_MAGIC);
+        state.push(STATE_VERSION);
+        state.extend_from_slice(self.memory.read_chunk(0, memory::SIZE));
+        state.extend_from_slice(&self.regs);
+        for word in [self.pc, self.i, self.start] {
+            state.extend_from_slice(&word.to_be_bytes());
+        }
+        state.extend_from_slice(&[self.dt, self.st]);
+
+        state.extend_from_slice(&(self.stack.len() as u16).to_be_bytes());
+        state.extend_from_slice(&(self.sp as u16).to_be_bytes());
+        for addr in &self.stack {
+            state.extend_from_slice(&addr.to_be_bytes());
+        }
+
+        state.extend_from_slice(&self.keys);
+        state.push(self.key_pressed.unwrap_or(0xFF));
+
+        let policy = match self.quirks.stack_policy {
+            StackPolicy::Error => 0,
+            StackPolicy::Wrap => 1,
+            StackPolicy::Ignore => 2,
+        };
+        state.extend_from_slice(&[
+            self.quirks.display_wait as u8,
+            self.quirks.wrap_sprites as u8,
+            self.quirks.collision_rows as u8,
+            policy,
+        ]);
+        state.extend_from_slice(&[self.hires as u8, self.vblank_wait as u8, self.buzzer as u8]);
+
+        // The address written, or the opcode
+        let (kind, pc, detail) = match self.fault {
+            None => (0, 0, 0),
+            Some(Fault::ProtectedWrite { pc, address }) => (1, pc, address),
+            Some(Fault::StackOverflow { pc }) => (2, pc, 0),
+            Some(Fault::StackUnderflow { pc }) => (3, pc, 0),
+            Some(Fault::InvalidOpcode { pc, opcode }) => (4, pc, opcode),
+        };
+        state.push(kind);
+        state.extend_from_slice(&pc.to_be_bytes());
+        state.extend_from_slice(&detail.to_be_bytes());
+
+        let (_, height) = self.vram.resolution();
+        for plane in 0..PLANES {
+            for y in 0..height {
+                state.extend_from_slice(&self.vram.row(plane, y).to_be_bytes());
+            }
+        }
+        state
+    }
+
+    /// Restores a state saved by `save_state`. On error the CPU is left
+    /// unchanged.
+    pub fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        let mut reader = StateReader { data: state };
+        if reader.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(invalid_state("not a save state"));
+        }
+        let version = reader.u8()?;
+        if version != STATE_VERSION {
+            return Err(invalid_state(&format!(
+                "save state version {} is not supported",
+                version
+            )));
+        }
+
+        let data = reader.bytes(memory::SIZE)?;
+        let regs = reader.bytes(16)?.to_vec();
+        let pc = reader.u16()?;
+        let i = reader.u16()?;
+        let start = reader.u16()?;
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+        if pc as usize >= memory::SIZE || start as usize >= memory::SIZE {
+            return Err(invalid_state("address out of memory"));
+        }
+
+        let depth = reader.u16()? as usize;
+        let sp = reader.u16()? as usize;
+        if sp > depth {
+            return Err(invalid_state("stack pointer past the stack"));
+        }
+        let stack = (0..depth)
+            .map(|_| reader.u16())
+            .collect::<io::Result<Vec<u16>>>()?;
+        if stack.iter().any(|&addr| addr as usize >= memory::SIZE) {
+            return Err(invalid_state("return address out of memory"));
+        }
+
+        let keys = reader.bytes(16)?.to_vec();
+        let key_pressed = match reader.u8()? {
+            0xFF => None,
+            key if key < 16 => Some(key),
+            _ => return Err(invalid_state("invalid key")),
+        };
+        if keys.iter().any(|&key| key > 1) {
+            return Err(invalid_state("invalid key"));
+        }
+
+        let display_wait = reader.flag()?;
+        let wrap_sprites = reader.flag()?;
+        let collision_rows = reader.flag()?;
+        let stack_policy = match reader.u8()? {
+            0 => StackPolicy::Error,
+            1 => StackPolicy::Wrap,
+            2 => StackPolicy::Ignore,
+            _ => return Err(invalid_state("invalid stack policy")),
+        };
+        let quirks = Quirks {
+            display_wait,
+            wrap_sprites,
+            collision_rows,
+            stack_depth: depth,
+            stack_policy,
+        };
+        let hires = reader.flag()?;
+        let vblank_wait = reader.flag()?;
+        let buzzer = reader.flag()?;
+
+        let kind = reader.u8()?;
+        let fault_pc = reader.u16()?;
+        let detail = reader.u16()?;
+        let fault = match kind {
+            0 => None,
+            1 => Some(Fault::ProtectedWrite {
+                pc: fault_pc,
+                address: detail,
+            }),
+            2 => Some(Fault::StackOverflow { pc: fault_pc }),
+            3 => Some(Fault::StackUnderflow { pc: fault_pc }),
+            4 => Some(Fault::InvalidOpcode {
+                pc: fault_pc,
+                opcode: detail,
+            }),
+            _ => return Err(invalid_state("invalid fault")),
+        };
+        // Unused fields are 0, so that the state saves back the same
+        let unused = match fault {
+            None => fault_pc != 0 || detail != 0,
+            Some(Fault::StackOverflow { .. } | Fault::StackUnderflow { .. }) => detail != 0,
+            Some(_) => false,
+        };
+        if unused || fault_pc as usize >= memory::SIZE {
+            return Err(invalid_state("invalid fault"));
+        }
+
+        let mut vram = if hires {
+            Framebuffer::new(HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            Framebuffer::new(WIDTH, HEIGHT)
+        };
+        let (width, height) = vram.resolution();
+        let outside = u128::MAX.checked_shr(width as u32).unwrap_or(0);
+        for plane in 0..PLANES {
+            for y in 0..height {
+                let row = reader.u128()?;
+                if row & outside != 0 {
+                    return Err(invalid_state("pixels outside the display"));
+                }
+                vram.xor_row(plane, y, row);
+            }
+        }
+        if !reader.data.is_empty() {
+            return Err(invalid_state("trailing bytes after the save state"));
+        }
+
+        self.memory.load(0, data);
+        self.regs = regs;
+        self.pc = pc;
+        self.i = i;
+        self.start = start;
+        self.dt = dt;
+        self.st = st;
+        self.stack = stack;
+        self.sp = sp;
+        self.keys = keys;
+        self.key_pressed = key_pressed;
+        self.quirks = quirks;
+        self.hires = hires;
+        self.vblank_wait = vblank_wait;
+        self.buzzer = buzzer;
+        self.fault = fault;
+        self.vram = vram;
+        self.invalidate(0..memory::SIZE);
+        Ok(())
+    }
+}
+
+/// Reads the fields of a save state in order
+struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(invalid_state("save state is truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn flag(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_state("invalid flag")),
+        }
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u128(&mut self) -> io::Result<u128> {
+        Ok(u128::from_be_bytes(self.bytes(16)?.try_into().unwrap()))
+    }
+}
+
+fn invalid_state(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Default for Cpu {
+    fn default() -> Cpu {
+        Cpu::new()
     }
 }
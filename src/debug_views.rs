@@ -0,0 +1,358 @@
+//! Debug windows of the SDL frontend, each toggled by a function key:
+//!
+//! - F1: hex view of the whole memory, with PC, I and recent writes
+//!   highlighted
+//! - F2: the memory at I drawn as 8xN sprites
+//! - F3: registers, stack and the disassembly around PC
+//!
+//! The windows are redrawn after every frame.
+
+use crate::bitmap::{Bitmap, CHAR_HEIGHT, CHAR_WIDTH};
+use r_chip_8::memory::{self, Access, MemoryHook};
+use r_chip_8::{Cpu, Instruction, Rgba};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use sdl2::VideoSubsystem;
+
+const BACKGROUND: Rgba = [0x10, 0x10, 0x10, 0xFF];
+const TEXT: Rgba = [0xC0, 0xC0, 0xC0, 0xFF];
+const DIM: Rgba = [0x50, 0x50, 0x50, 0xFF];
+const PC_COLOR: Rgba = [0x40, 0xE0, 0x40, 0xFF];
+const I_COLOR: Rgba = [0x60, 0xA0, 0xFF, 0xFF];
+
+/// Bytes per line of the memory view
+const BYTES_PER_LINE: usize = 64;
+
+/// How many frames a write stays highlighted, fading out
+const WRITE_FADE_FRAMES: u8 = 30;
+
+/// Sprites shown by the sprite view, each 16 bytes from I on
+const SPRITE_COLUMNS: usize = 4;
+const SPRITE_ROWS: usize = 16;
+/// Size of a sprite pixel
+const SPRITE_PIXEL: usize = 5;
+
+/// Instructions disassembled before and after PC in the register view
+const CONTEXT: u16 = 4;
+/// Stack frames listed by the register view, innermost first. Deeper stacks
+/// end with a count of the frames left out, keeping the disassembly in view.
+const STACK_FRAMES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewKind {
+    Memory,
+    Sprites,
+    Registers,
+}
+
+impl ViewKind {
+    /// The view toggled by `keycode`
+    pub fn from_keycode(keycode: Keycode) -> Option<ViewKind> {
+        match keycode {
+            Keycode::F1 => Some(ViewKind::Memory),
+            Keycode::F2 => Some(ViewKind::Sprites),
+            Keycode::F3 => Some(ViewKind::Registers),
+            _ => None,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ViewKind::Memory => "rCHIP-8 memory",
+            ViewKind::Sprites => "rCHIP-8 sprites at I",
+            ViewKind::Registers => "rCHIP-8 registers",
+        }
+    }
+
+    /// Size of the view in bitmap pixels, and how much it is scaled up
+    fn size(&self) -> (usize, usize, u32) {
+        match self {
+            ViewKind::Memory => (
+                (4 + BYTES_PER_LINE * 2) * CHAR_WIDTH + BYTES_PER_LINE + 4,
+                (memory::SIZE / BYTES_PER_LINE) * CHAR_HEIGHT + 4,
+                2,
+            ),
+            ViewKind::Sprites => (
+                SPRITE_COLUMNS * (8 * SPRITE_PIXEL + 4 * CHAR_WIDTH) + 4,
+                SPRITE_ROWS * SPRITE_PIXEL + CHAR_HEIGHT * 2 + 4,
+                2,
+            ),
+            ViewKind::Registers => (30 * CHAR_WIDTH + 4, 40 * CHAR_HEIGHT + 4, 3),
+        }
+    }
+}
+
+/// Input events meant for the debug views, collected by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugEvent {
+    Toggle(ViewKind),
+    /// A window other than the main one was closed
+    Close(u32),
+}
+
+/// Marks the bytes the program writes, fading out over `WRITE_FADE_FRAMES`
+/// frames. Installed as the memory hook while the memory view is open.
+struct RecentWrites {
+    age: Vec<u8>,
+}
+
+impl MemoryHook for RecentWrites {
+    fn access(&mut self, access: Access, address: u16, _value: u8) {
+        if access == Access::Write {
+            self.age[address as usize] = WRITE_FADE_FRAMES;
+        }
+    }
+}
+
+struct View {
+    kind: ViewKind,
+    canvas: WindowCanvas,
+    texture_creator: TextureCreator<WindowContext>,
+}
+
+pub struct DebugViews {
+    video: VideoSubsystem,
+    views: Vec<View>,
+}
+
+impl DebugViews {
+    pub fn new(video: VideoSubsystem) -> DebugViews {
+        DebugViews {
+            video,
+            views: Vec::new(),
+        }
+    }
+
+    pub fn handle(&mut self, event: DebugEvent, cpu: &mut Cpu) {
+        match event {
+            DebugEvent::Toggle(kind) => self.toggle(kind, cpu),
+            DebugEvent::Close(window_id) => {
+                if let Some(view) = self
+                    .views
+                    .iter()
+                    .find(|view| view.canvas.window().id() == window_id)
+                {
+                    self.toggle(view.kind, cpu);
+                }
+            }
+        }
+    }
+
+    fn toggle(&mut self, kind: ViewKind, cpu: &mut Cpu) {
+        if let Some(index) = self.views.iter().position(|view| view.kind == kind) {
+            // Dropping the canvas closes the window
+            self.views.remove(index);
+            if kind == ViewKind::Memory {
+                cpu.memory_mut().remove_hook::<RecentWrites>();
+            }
+            return;
+        }
+
+        let (width, height, scale) = kind.size();
+        let window = match self
+            .video
+            .window(kind.title(), width as u32 * scale, height as u32 * scale)
+            .build()
+        {
+            Ok(window) => window,
+            Err(e) => {
+                eprintln!("Cannot open the {} window: {}", kind.title(), e);
+                return;
+            }
+        };
+        let canvas = window.into_canvas().build().unwrap();
+        let texture_creator = canvas.texture_creator();
+        if kind == ViewKind::Memory {
+            // Next to any other hook, such as GDB's watchpoints
+            cpu.memory_mut().add_hook(Box::new(RecentWrites {
+                age: vec![0; memory::SIZE],
+            }));
+        }
+        self.views.push(View {
+            kind,
+            canvas,
+            texture_creator,
+        });
+    }
+
+    /// Redraws the open views. Called once per frame.
+    pub fn update(&mut self, cpu: &mut Cpu) {
+        if self.views.is_empty() {
+            return;
+        }
+        let recent_writes = match cpu.hook_mut::<RecentWrites>() {
+            Some(writes) => {
+                let age = writes.age.clone();
+                for age in &mut writes.age {
+                    *age = age.saturating_sub(1);
+                }
+                age
+            }
+            None => vec![0; memory::SIZE],
+        };
+
+        for view in &mut self.views {
+            let bitmap = match view.kind {
+                ViewKind::Memory => memory_view(cpu, &recent_writes),
+                ViewKind::Sprites => sprite_view(cpu),
+                ViewKind::Registers => register_view(cpu),
+            };
+            let mut texture = view
+                .texture_creator
+                .create_texture_streaming(
+                    PixelFormatEnum::RGBA32,
+                    bitmap.width() as u32,
+                    bitmap.height() as u32,
+                )
+                .unwrap();
+            texture
+                .update(None, bitmap.rgba(), bitmap.width() * 4)
+                .unwrap();
+            view.canvas.copy(&texture, None, None).unwrap();
+            view.canvas.present();
+        }
+    }
+}
+
+fn memory_view(cpu: &Cpu, recent_writes: &[u8]) -> Bitmap {
+    let (width, height, _) = ViewKind::Memory.size();
+    let mut bitmap = Bitmap::new(width, height, BACKGROUND);
+    let memory = cpu.memory();
+    let byte_width = 2 * CHAR_WIDTH + 1;
+
+    for line in 0..memory::SIZE / BYTES_PER_LINE {
+        let y = 2 + line * CHAR_HEIGHT;
+        let start = line * BYTES_PER_LINE;
+        bitmap.text(2, y, &format!("{:03X}", start), DIM);
+
+        for (col, byte) in memory.read_chunk(start, BYTES_PER_LINE).iter().enumerate() {
+            let address = (start + col) as u16;
+            let x = 2 + 4 * CHAR_WIDTH + col * byte_width;
+
+            let age = recent_writes[address as usize];
+            if age > 0 {
+                let red = (0x30 + 0xC0 * age as usize / WRITE_FADE_FRAMES as usize) as u8;
+                bitmap.fill_rect(x - 1, y - 1, byte_width, CHAR_HEIGHT, [red, 0, 0, 0xFF]);
+            }
+            let color = if address == cpu.pc() || address == cpu.pc() + 1 {
+                PC_COLOR
+            } else if address == cpu.i() {
+                I_COLOR
+            } else if *byte == 0 {
+                DIM
+            } else {
+                TEXT
+            };
+            bitmap.text(x, y, &format!("{:02X}", byte), color);
+        }
+    }
+    bitmap
+}
+
+fn sprite_view(cpu: &Cpu) -> Bitmap {
+    let (width, height, _) = ViewKind::Sprites.size();
+    let mut bitmap = Bitmap::new(width, height, BACKGROUND);
+    let memory = cpu.memory();
+    bitmap.text(2, 2, &format!("I = {:03X}", cpu.i()), I_COLOR);
+
+    let column_width = 8 * SPRITE_PIXEL + 4 * CHAR_WIDTH;
+    for column in 0..SPRITE_COLUMNS {
+        let x0 = 2 + column * column_width;
+        let y0 = 2 + 2 * CHAR_HEIGHT;
+        for row in 0..SPRITE_ROWS {
+            let address = cpu.i() as usize + column * SPRITE_ROWS + row;
+            if address >= memory::SIZE {
+                break;
+            }
+            if row == 0 {
+                bitmap.text(x0, y0 - CHAR_HEIGHT, &format!("{:03X}", address), DIM);
+            }
+            let byte = memory.read(address);
+            for bit in 0..8 {
+                let color = if byte & (0x80 >> bit) != 0 {
+                    TEXT
+                } else {
+                    [0x20, 0x20, 0x20, 0xFF]
+                };
+                bitmap.fill_rect(
+                    x0 + bit * SPRITE_PIXEL,
+                    y0 + row * SPRITE_PIXEL,
+                    SPRITE_PIXEL - 1,
+                    SPRITE_PIXEL - 1,
+                    color,
+                );
+            }
+        }
+    }
+    bitmap
+}
+
+fn register_view(cpu: &Cpu) -> Bitmap {
+    let (width, height, _) = ViewKind::Registers.size();
+    let mut bitmap = Bitmap::new(width, height, BACKGROUND);
+    let mut lines: Vec<(String, Rgba)> = vec![
+        (format!("PC {:03X}   I {:03X}", cpu.pc(), cpu.i()), TEXT),
+        (format!("DT  {:02X}  ST  {:02X}", cpu.dt(), cpu.st()), TEXT),
+    ];
+    if let Some(fault) = cpu.fault() {
+        lines.push((format!("FAULT {}", fault), [0xFF, 0x40, 0x40, 0xFF]));
+    }
+    lines.push((String::new(), TEXT));
+
+    for (row, regs) in cpu.registers().chunks(4).enumerate() {
+        let regs: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(col, v)| format!("V{:X} {:02X}", row * 4 + col, v))
+            .collect();
+        lines.push((regs.join("  "), TEXT));
+    }
+
+    lines.push((String::new(), TEXT));
+    let backtrace = cpu.backtrace();
+    lines.push((
+        format!("STACK ({}/{})", backtrace.len(), cpu.stack_depth()),
+        TEXT,
+    ));
+    let listed = if backtrace.len() > STACK_FRAMES {
+        STACK_FRAMES - 1
+    } else {
+        backtrace.len()
+    };
+    for (depth, frame) in backtrace.iter().take(listed).enumerate() {
+        lines.push((
+            format!("  {:2}  {}", backtrace.len() - 1 - depth, frame),
+            TEXT,
+        ));
+    }
+    if listed < backtrace.len() {
+        lines.push((format!("  ... {} more", backtrace.len() - listed), DIM));
+    }
+
+    lines.push((String::new(), TEXT));
+    let memory = cpu.memory();
+    let start = cpu.pc().saturating_sub(CONTEXT * 2);
+    for addr in (start..=cpu.pc() + CONTEXT * 2).step_by(2) {
+        if addr as usize + 1 >= memory::SIZE {
+            break;
+        }
+        let opcode =
+            (memory.read(addr as usize) as u16) << 8 | memory.read(addr as usize + 1) as u16;
+        let (marker, color) = if addr == cpu.pc() {
+            ('>', PC_COLOR)
+        } else {
+            (' ', TEXT)
+        };
+        lines.push((
+            format!("{} {:03X}  {}", marker, addr, Instruction::decode(opcode)),
+            color,
+        ));
+    }
+
+    for (row, (line, color)) in lines.iter().enumerate() {
+        bitmap.text(2, 2 + row * CHAR_HEIGHT, line, *color);
+    }
+    bitmap
+}
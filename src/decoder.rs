@@ -0,0 +1,198 @@
+//! Decoding opcodes into instructions, mainly for disassembly.
+//!
+//! Mnemonics follow Cowgod's Chip-8 Technical Reference, like the interpreter
+//! trace output.
+
+use std::fmt;
+
+/// A decoded instruction. `x` and `y` are register indexes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn
+    Sys(u16),
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00FE (SUPER-CHIP)
+    Low,
+    /// 00FF (SUPER-CHIP)
+    High,
+    /// 1nnn
+    Jp(u16),
+    /// 2nnn
+    Call(u16),
+    /// 3xkk
+    SeByte(u8, u8),
+    /// 4xkk
+    SneByte(u8, u8),
+    /// 5xy0
+    SeReg(u8, u8),
+    /// 6xkk
+    LdByte(u8, u8),
+    /// 7xkk
+    AddByte(u8, u8),
+    /// 8xy0
+    LdReg(u8, u8),
+    /// 8xy1
+    Or(u8, u8),
+    /// 8xy2
+    And(u8, u8),
+    /// 8xy3
+    Xor(u8, u8),
+    /// 8xy4
+    AddReg(u8, u8),
+    /// 8xy5
+    Sub(u8, u8),
+    /// 8xy6
+    Shr(u8, u8),
+    /// 8xy7
+    Subn(u8, u8),
+    /// 8xyE
+    Shl(u8, u8),
+    /// 9xy0
+    SneReg(u8, u8),
+    /// Annn
+    LdI(u16),
+    /// Bnnn
+    JpV0(u16),
+    /// Cxkk
+    Rnd(u8, u8),
+    /// Dxyn
+    Drw(u8, u8, u8),
+    /// Ex9E
+    Skp(u8),
+    /// ExA1
+    Sknp(u8),
+    /// Fx07
+    LdVxDt(u8),
+    /// Fx0A
+    LdVxK(u8),
+    /// Fx15
+    LdDtVx(u8),
+    /// Fx18
+    LdStVx(u8),
+    /// Fx1E
+    AddI(u8),
+    /// Fx29
+    LdF(u8),
+    /// Fx33
+    LdB(u8),
+    /// Fx55
+    LdIVx(u8),
+    /// Fx65
+    LdVxI(u8),
+    /// Anything the interpreter does not implement
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Instruction {
+        use Instruction::*;
+
+        let nnn = opcode & 0x0FFF;
+        let n = (opcode & 0xF) as u8;
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let kk = (opcode & 0xFF) as u8;
+
+        match opcode >> 12 {
+            0x0 => match nnn {
+                0x0E0 => Cls,
+                0x0EE => Ret,
+                0x0FE => Low,
+                0x0FF => High,
+                _ => Sys(nnn),
+            },
+            0x1 => Jp(nnn),
+            0x2 => Call(nnn),
+            0x3 => SeByte(x, kk),
+            0x4 => SneByte(x, kk),
+            0x5 => SeReg(x, y),
+            0x6 => LdByte(x, kk),
+            0x7 => AddByte(x, kk),
+            0x8 => match n {
+                0x0 => LdReg(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddReg(x, y),
+                0x5 => Sub(x, y),
+                0x6 => Shr(x, y),
+                0x7 => Subn(x, y),
+                0xE => Shl(x, y),
+                _ => Unknown(opcode),
+            },
+            0x9 => SneReg(x, y),
+            0xA => LdI(nnn),
+            0xB => JpV0(nnn),
+            0xC => Rnd(x, kk),
+            0xD => Drw(x, y, n),
+            0xE => match kk {
+                0x9E => Skp(x),
+                0xA1 => Sknp(x),
+                _ => Unknown(opcode),
+            },
+            0xF => match kk {
+                0x07 => LdVxDt(x),
+                0x0A => LdVxK(x),
+                0x15 => LdDtVx(x),
+                0x18 => LdStVx(x),
+                0x1E => AddI(x),
+                0x29 => LdF(x),
+                0x33 => LdB(x),
+                0x55 => LdIVx(x),
+                0x65 => LdVxI(x),
+                _ => Unknown(opcode),
+            },
+            _ => Unknown(opcode),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Sys(nnn) => write!(f, "SYS {:03X}", nnn),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(nnn) => write!(f, "JP {:03X}", nnn),
+            Call(nnn) => write!(f, "CALL {:03X}", nnn),
+            SeByte(x, kk) => write!(f, "SE V{:X}, {:02X}", x, kk),
+            SneByte(x, kk) => write!(f, "SNE V{:X}, {:02X}", x, kk),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdByte(x, kk) => write!(f, "LD V{:X}, {:02X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, {:02X}", x, kk),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, _) => write!(f, "SHR V{:X}", x),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, _) => write!(f, "SHL V{:X}", x),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(nnn) => write!(f, "LD I, {:03X}", nnn),
+            JpV0(nnn) => write!(f, "JP V0, {:03X}", nnn),
+            Rnd(x, kk) => write!(f, "RND V{:X}, {:02X}", x, kk),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {:X}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LdF(x) => write!(f, "LD F, V{:X}", x),
+            LdB(x) => write!(f, "LD B, V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            Unknown(opcode) => write!(f, "DW {:04X}", opcode),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+/// An RGBA colour, one byte per channel
+pub type Rgba = [u8; 4];
+
+/// Number of bit planes. Plain CHIP-8 and SUPER-CHIP only draw to the first
+/// one; XO-CHIP combines two planes into four colours.
+pub const PLANES: usize = 2;
+
+/// Number of colours a pixel can take, one per combination of plane bits
+pub const COLORS: usize = 1 << PLANES;
+
+/// Widest supported resolution, bounded by the `u128` row representation
+pub const MAX_WIDTH: usize = 128;
+
+/// The display memory.
+///
+/// Every plane stores one `u128` per row, with column 0 in the most significant
+/// bit. Resolutions narrower than 128 pixels only use the upper `width` bits.
+///
+/// Two framebuffers are equal if they hold the same image, regardless of
+/// their dirty flags.
+#[derive(Debug, Clone, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: [Vec<u128>; PLANES],
+    dirty: bool,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        assert!(
+            width > 0 && width <= MAX_WIDTH,
+            "unsupported width {}",
+            width
+        );
+        Framebuffer {
+            width,
+            height,
+            planes: [vec![0; height], vec![0; height]],
+            dirty: true,
+        }
+    }
+
+    /// Display size as (width, height)
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Changes the resolution, clearing every plane
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Framebuffer::new(width, height);
+    }
+
+    /// Clears every plane
+    pub fn clear(&mut self) {
+        for plane in self.planes.iter_mut() {
+            plane.fill(0);
+        }
+        self.dirty = true;
+    }
+
+    /// Colour index of the pixel at (`x`, `y`): bit `p` is set if the pixel is
+    /// set in plane `p`
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let bit = Self::column_bit(x);
+        self.planes.iter().enumerate().fold(0, |acc, (p, plane)| {
+            acc | (((plane[y] & bit) != 0) as u8) << p
+        })
+    }
+
+    /// Row `y` of `plane`, column 0 in the most significant bit
+    pub fn row(&self, plane: usize, y: usize) -> u128 {
+        self.planes[plane][y]
+    }
+
+    /// XORs `bits` onto row `y` of `plane` and returns whether any set pixel
+    /// was erased.
+    pub fn xor_row(&mut self, plane: usize, y: usize, bits: u128) -> bool {
+        let row = &mut self.planes[plane][y];
+        let collision = *row & bits != 0;
+        *row ^= bits;
+        self.dirty |= bits != 0;
+        collision
+    }
+
+    /// Positions a 16 pixel wide sprite row (column 0 in the most significant
+    /// bit) at column `x`, as a row mask for `xor_row`.
+    ///
+    /// Pixels past the right edge either wrap around to the left edge or are
+    /// dropped.
+    pub fn place(&self, bits: u16, x: usize, wrap: bool) -> u128 {
+        let sprite = (bits as u128) << (MAX_WIDTH - 16);
+        let placed = sprite >> x;
+        let inside = placed & self.row_mask();
+        if !wrap {
+            return inside;
+        }
+
+        let outside = if self.width < MAX_WIDTH {
+            (placed & !self.row_mask()) << self.width
+        } else {
+            0
+        };
+        // Pixels shifted out past column 127
+        let spilled = if x > 0 { sprite << (MAX_WIDTH - x) } else { 0 };
+
+        inside | outside | spilled
+    }
+
+    /// Whether the display changed since the last `take_dirty`
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns whether the display changed since the last call, and resets
+    /// the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Exports the display as `width * height` RGBA pixels, row by row,
+    /// looking up every pixel's colour index in `palette`.
+    pub fn as_rgba(&self, palette: &[Rgba; COLORS]) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.width * self.height * 4);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                rgba.extend_from_slice(&palette[self.pixel(x, y) as usize]);
+            }
+        }
+        rgba
+    }
+
+    fn row_mask(&self) -> u128 {
+        !0 << (MAX_WIDTH - self.width)
+    }
+
+    fn column_bit(x: usize) -> u128 {
+        1 << (MAX_WIDTH - 1 - x)
+    }
+}
+
+impl PartialEq for Framebuffer {
+    fn eq(&self, other: &Framebuffer) -> bool {
+        self.resolution() == other.resolution() && self.planes == other.planes
+    }
+}
@@ -0,0 +1,575 @@
+//! A GDB remote serial protocol stub, to debug a running ROM with GDB or
+//! any other RSP client.
+//!
+//! Registers, in `g` packet order:
+//!
+//! | number | name    | size                     |
+//! |--------|---------|--------------------------|
+//! | 0-15   | v0-vf   | 8 bits                   |
+//! | 16     | i       | 16 bits                  |
+//! | 17     | pc      | 16 bits                  |
+//! | 18     | sp      | 8 bits (stack depth, read only) |
+//! | 19     | dt      | 8 bits                   |
+//! | 20     | st      | 8 bits                   |
+//!
+//! 16-bit registers are big-endian, like CHIP-8 instructions. The target
+//! description is also served as `target.xml`. Memory is a 64 KiB address
+//! space of which the first 4 KiB exist; accesses beyond fail.
+//!
+//! Supported: reading and writing registers and memory, software (`Z0`) and
+//! hardware (`Z1`) breakpoints, write/read/access watchpoints (`Z2`-`Z4`),
+//! `s`tep, `c`ontinue and interrupting with Ctrl-C. Breakpoints are not
+//! written into memory; the stub checks PC before every instruction.
+//!
+//! The stub is a [`Debugger`]: install it with `Machine::set_debugger` and
+//! the ROM keeps being displayed while it is halted, or call
+//! [`GdbStub::run_frame`] in a loop to debug without a frontend.
+
+use crate::cpu::{Cpu, Fault};
+use crate::host::Debugger;
+use crate::memory::{self, Access};
+use crate::watch::{Hit, Watchpoint, Watchpoints};
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/// How long a halted target waits for packets per frame, so that the
+/// frontend stays responsive
+const HALTED_WAIT: Duration = Duration::from_millis(10);
+
+/// Number of registers, see the module documentation
+const REGISTERS: usize = 21;
+
+/// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rchip8.cpu">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Halted,
+    Running,
+}
+
+/// Why the target stopped
+enum Stop {
+    Signal(u8),
+    Watchpoint(Hit),
+}
+
+/// Outcome of executing instructions
+enum Run {
+    /// The instruction budget was used up, or the frame ended early
+    Done,
+    Stopped(Stop),
+}
+
+pub struct GdbStub {
+    /// `None` once the client detached; the CPU then runs freely.
+    stream: Option<TcpStream>,
+    /// Received bytes not processed yet
+    input: Vec<u8>,
+    state: State,
+    breakpoints: BTreeSet<u16>,
+    /// Set when resuming from a breakpoint, so it does not trigger again
+    /// before the instruction under it executed
+    skip_breakpoint: bool,
+}
+
+impl GdbStub {
+    /// Waits for a client to connect on `addr`.
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        GdbStub::new(stream)
+    }
+
+    /// Debugs over an accepted connection. The target starts halted.
+    pub fn new(stream: TcpStream) -> io::Result<GdbStub> {
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream: Some(stream),
+            input: Vec::new(),
+            state: State::Halted,
+            breakpoints: BTreeSet::new(),
+            skip_breakpoint: false,
+        })
+    }
+
+    /// Whether a client is still attached
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Handles the client's requests and, unless the target is halted, runs
+    /// one frame of up to `ipf` instructions, stopping at breakpoints.
+    pub fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        if self.stream.is_none() {
+            cpu.run_frame(ipf);
+            return;
+        }
+        if let Err(e) = self.serve(cpu, ipf) {
+            if e.kind() != io::ErrorKind::UnexpectedEof {
+                eprintln!("GDB connection lost: {}", e);
+            }
+            self.detach(cpu);
+        }
+    }
+
+    fn serve(&mut self, cpu: &mut Cpu, ipf: u32) -> io::Result<()> {
+        if self.state == State::Halted {
+            let deadline = Instant::now() + HALTED_WAIT;
+            while self.state == State::Halted && self.stream.is_some() {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(());
+                }
+                self.receive(Some(deadline - now))?;
+                self.process(cpu)?;
+            }
+        } else {
+            self.receive(None)?;
+            self.process(cpu)?;
+        }
+
+        if self.state == State::Running {
+            match self.execute(cpu, ipf) {
+                Run::Done => cpu.end_frame(),
+                Run::Stopped(stop) => self.stop(stop)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs up to `budget` instructions, checking breakpoints, watchpoints
+    /// and faults before and after each one
+    fn execute(&mut self, cpu: &mut Cpu, budget: u32) -> Run {
+        for _ in 0..budget {
+            if let Some(fault) = cpu.fault() {
+                return Run::Stopped(Stop::Signal(signal(fault)));
+            }
+            if !std::mem::take(&mut self.skip_breakpoint) && self.breakpoints.contains(&cpu.pc()) {
+                return Run::Stopped(Stop::Signal(SIGTRAP));
+            }
+            cpu.step();
+            if let Some(hit) = self.take_watchpoint_hit(cpu) {
+                return Run::Stopped(Stop::Watchpoint(hit));
+            }
+            if cpu.in_vblank_wait() {
+                break;
+            }
+        }
+        Run::Done
+    }
+
+    fn take_watchpoint_hit(&mut self, cpu: &mut Cpu) -> Option<Hit> {
+        let watchpoints = cpu.hook_mut::<Watchpoints>()?;
+        watchpoints.take_hits().into_iter().next()
+    }
+
+    /// Halts a running target and tells the client
+    fn stop(&mut self, stop: Stop) -> io::Result<()> {
+        self.state = State::Halted;
+        self.send(&stop_reply(stop))
+    }
+
+    /// Lets the CPU run freely, without the breakpoints and watchpoints
+    fn detach(&mut self, cpu: &mut Cpu) {
+        self.stream = None;
+        self.input.clear();
+        self.state = State::Running;
+        self.breakpoints.clear();
+        cpu.memory_mut().remove_hook::<Watchpoints>();
+    }
+
+    /// Reads what the client sent: everything available if `wait` is
+    /// `None`, otherwise whatever arrives within `wait`.
+    fn receive(&mut self, wait: Option<Duration>) -> io::Result<()> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+        stream.set_nonblocking(wait.is_none())?;
+        if wait.is_some() {
+            stream.set_read_timeout(wait)?;
+        }
+        let mut buffer = [0; 4096];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    self.input.extend_from_slice(&buffer[..n]);
+                    if wait.is_some() {
+                        return Ok(());
+                    }
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(())
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Handles the complete packets in the input buffer
+    fn process(&mut self, cpu: &mut Cpu) -> io::Result<()> {
+        loop {
+            // Acknowledgements need no answer
+            let start = self
+                .input
+                .iter()
+                .position(|&b| b != b'+' && b != b'-')
+                .unwrap_or(self.input.len());
+            self.input.drain(..start);
+
+            match self.input.first() {
+                None => return Ok(()),
+                Some(0x03) => {
+                    self.input.remove(0);
+                    if self.state == State::Running {
+                        self.stop(Stop::Signal(SIGINT))?;
+                    }
+                }
+                Some(b'$') => {
+                    let Some(hash) = self.input.iter().position(|&b| b == b'#') else {
+                        return Ok(());
+                    };
+                    if self.input.len() < hash + 3 {
+                        return Ok(());
+                    }
+                    let packet: Vec<u8> = self.input.drain(..hash + 3).collect();
+                    let data = &packet[1..hash];
+                    let checksum = std::str::from_utf8(&packet[hash + 1..])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if checksum != Some(checksum_of(data)) {
+                        self.write_raw(b"-")?;
+                        continue;
+                    }
+                    self.write_raw(b"+")?;
+                    let data = String::from_utf8_lossy(data).into_owned();
+                    if let Some(reply) = self.handle(cpu, &data) {
+                        self.send(&reply)?;
+                    }
+                }
+                // Garbage between packets
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    /// Executes a command and returns the reply, or `None` if there is none
+    /// (after detaching) or it is sent later (when a continued target stops)
+    fn handle(&mut self, cpu: &mut Cpu, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        match command {
+            "c" => {
+                if let Some(addr) = parse_hex(args) {
+                    cpu.set_pc(addr as u16);
+                }
+                self.skip_breakpoint = true;
+                self.state = State::Running;
+                None
+            }
+            "D" => {
+                let _ = self.send(&ok());
+                self.detach(cpu);
+                None
+            }
+            "k" => {
+                self.detach(cpu);
+                None
+            }
+            _ => Some(self.reply(cpu, command, args).unwrap_or_else(error)),
+        }
+    }
+
+    /// Executes any other command and returns the reply, or `None` if the
+    /// packet is malformed
+    fn reply(&mut self, cpu: &mut Cpu, command: &str, args: &str) -> Option<String> {
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTERS).map(|n| read_register(cpu, n)).collect(),
+            "G" => {
+                let bytes = decode_hex(args)?;
+                let mut rest = bytes.as_slice();
+                for n in 0..REGISTERS {
+                    let size = register_size(n);
+                    if rest.len() < size {
+                        return Some(error());
+                    }
+                    write_register(cpu, n, &rest[..size]);
+                    rest = &rest[size..];
+                }
+                ok()
+            }
+            "p" => match parse_hex(args) {
+                Some(n) if (n as usize) < REGISTERS => read_register(cpu, n as usize),
+                _ => error(),
+            },
+            "P" => {
+                let (n, value) = args.split_once('=')?;
+                let n = parse_hex(n)? as usize;
+                let value = decode_hex(value)?;
+                if n >= REGISTERS
+                    || value.len() != register_size(n)
+                    || !write_register(cpu, n, &value)
+                {
+                    error()
+                } else {
+                    ok()
+                }
+            }
+            "m" => {
+                let (addr, len) = args.split_once(',')?;
+                match read_memory(cpu, parse_hex(addr)?, parse_hex(len)?) {
+                    Some(bytes) => encode_hex(&bytes),
+                    None => error(),
+                }
+            }
+            "M" => {
+                let (range, data) = args.split_once(':')?;
+                let (addr, len) = range.split_once(',')?;
+                let (addr, len) = (parse_hex(addr)? as usize, parse_hex(len)? as usize);
+                let data = decode_hex(data)?;
+                if data.len() != len || addr + len > memory::SIZE {
+                    error()
+                } else {
+                    cpu.memory_mut().load(addr, &data);
+                    ok()
+                }
+            }
+            "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    cpu.set_pc(addr as u16);
+                }
+                let stop = self.execute_step(cpu).unwrap_or(Stop::Signal(SIGTRAP));
+                stop_reply(stop)
+            }
+            "Z" | "z" => self.breakpoint(cpu, command == "Z", args)?,
+            "H" => ok(),
+            "q" => query(args)?,
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    /// Executes one instruction for `s`, returning why it stopped if not
+    /// just because of the step
+    fn execute_step(&mut self, cpu: &mut Cpu) -> Option<Stop> {
+        self.skip_breakpoint = true;
+        match self.execute(cpu, 1) {
+            Run::Stopped(stop) => Some(stop),
+            Run::Done => cpu.fault().map(|fault| Stop::Signal(signal(fault))),
+        }
+    }
+
+    /// `Z`/`z` type,addr,kind
+    fn breakpoint(&mut self, cpu: &mut Cpu, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        // PC is 12 bits: a breakpoint past the end of memory would never hit
+        let addr = parse_hex(fields.next()?).filter(|&addr| (addr as usize) < memory::SIZE)? as u16;
+        let len = fields.next().and_then(parse_hex).unwrap_or(1).max(1) as u16;
+
+        let accesses: &[Access] = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return Some(ok());
+            }
+            "2" => &[Access::Write],
+            "3" => &[Access::Read],
+            "4" => &[Access::Read, Access::Write],
+            _ => return Some(String::new()),
+        };
+        let watchpoint = Watchpoint::new(addr..addr.saturating_add(len), accesses);
+        // Next to any other hook, such as the debug views'
+        let memory = cpu.memory_mut();
+        if memory.hook_mut::<Watchpoints>().is_none() {
+            memory.add_hook(Box::new(Watchpoints::new()));
+        }
+        let watchpoints = memory.hook_mut::<Watchpoints>()?;
+        if insert {
+            watchpoints.add(watchpoint);
+        } else {
+            watchpoints.remove(&watchpoint);
+        }
+        Some(ok())
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.write_raw(packet.as_bytes())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+        stream.set_nonblocking(false)?;
+        stream.write_all(bytes)
+    }
+}
+
+impl Debugger for GdbStub {
+    fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        GdbStub::run_frame(self, cpu, ipf);
+    }
+}
+
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Signal(signal) => format!("S{:02x}", signal),
+        Stop::Watchpoint(hit) => {
+            let kind = match hit.access {
+                Access::Write => "watch",
+                Access::Read => "rwatch",
+                Access::Execute => "awatch",
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.address)
+        }
+    }
+}
+
+/// The signal a fault stops the program with
+fn signal(fault: Fault) -> u8 {
+    match fault {
+        Fault::InvalidOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+/// Replies to `q` packets
+fn query(args: &str) -> Option<String> {
+    if args.starts_with("Supported") {
+        return Some("PacketSize=4000;qXfer:features:read+".to_string());
+    }
+    if args == "Attached" {
+        return Some("1".to_string());
+    }
+    if let Some(rest) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let (offset, len) = rest.split_once(',')?;
+        let (offset, len) = (parse_hex(offset)? as usize, parse_hex(len)? as usize);
+        let xml = TARGET_XML.as_bytes();
+        let start = offset.min(xml.len());
+        let end = (start + len).min(xml.len());
+        let chunk = String::from_utf8_lossy(&xml[start..end]);
+        let more = if end < xml.len() { 'm' } else { 'l' };
+        return Some(format!("{}{}", more, chunk));
+    }
+    Some(String::new())
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(cpu: &Cpu, n: usize) -> String {
+    match n {
+        0..=15 => format!("{:02x}", cpu.registers()[n]),
+        16 => format!("{:04x}", cpu.i()),
+        17 => format!("{:04x}", cpu.pc()),
+        18 => format!("{:02x}", cpu.stack().len()),
+        19 => format!("{:02x}", cpu.dt()),
+        _ => format!("{:02x}", cpu.st()),
+    }
+}
+
+/// Sets register `n` from its big-endian bytes. Returns false for the read
+/// only SP.
+fn write_register(cpu: &mut Cpu, n: usize, bytes: &[u8]) -> bool {
+    let word = bytes.iter().fold(0u16, |acc, &b| acc << 8 | b as u16);
+    match n {
+        0..=15 => cpu.set_register(n, bytes[0]),
+        16 => cpu.set_i(word),
+        17 => cpu.set_pc(word),
+        18 => return false,
+        19 => cpu.set_dt(bytes[0]),
+        _ => cpu.set_st(bytes[0]),
+    }
+    true
+}
+
+/// Up to `len` bytes from `addr`, cut off at the end of memory. Fails if
+/// `addr` itself is outside.
+fn read_memory(cpu: &Cpu, addr: u32, len: u32) -> Option<Vec<u8>> {
+    let addr = addr as usize;
+    if addr >= memory::SIZE {
+        return None;
+    }
+    let len = (len as usize).min(memory::SIZE - addr);
+    Some(cpu.memory().read_chunk(addr, len).to_vec())
+}
+
+fn ok() -> String {
+    "OK".to_string()
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn parse_hex(hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,239 @@
+//! The boundary between the interpreter and a frontend.
+//!
+//! A frontend implements [`Host`] for its display, audio, input and clock, and
+//! hands it to a [`Machine`], which owns the [`Cpu`] and drives the main loop.
+//! The SDL and terminal frontends, the [`Headless`] host and test hosts are
+//! interchangeable.
+
+use crate::cpu::Cpu;
+use crate::display::Framebuffer;
+use crate::scheduler::FrameScheduler;
+use std::any::Any;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `thread::sleep` is only accurate to about a millisecond, so the last part
+/// of a wait is spent spinning.
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+/// Sleeps for `duration` with sub-millisecond precision.
+pub fn spin_sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    if duration > SPIN_MARGIN {
+        thread::sleep(duration - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// State of the 16 keys of the hex keypad: bit `k` is set while key `k` is
+/// down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyState(pub u16);
+
+impl KeyState {
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.0 & (1 << key) != 0
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.0 |= 1 << key;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.0 &= !(1 << key);
+    }
+}
+
+/// What the interpreter needs from a frontend.
+pub trait Host {
+    /// Shows the display. Called once per loop iteration; the framebuffer is
+    /// `is_dirty()` if it changed since the previous call.
+    fn present(&mut self, framebuffer: &Framebuffer);
+
+    /// Starts (`true`) or stops (`false`) the buzzer. Only called when the
+    /// state changes.
+    fn beep(&mut self, on: bool);
+
+    /// Called after every emulated frame with whether the buzzer sounded
+    /// during it (`Cpu::buzzer`), for hosts that render or record audio
+    /// sample-accurately. The default does nothing.
+    fn audio_frame(&mut self, _buzzer: bool) {}
+
+    /// Processes pending input and returns the keys currently held down.
+    fn poll_input(&mut self) -> KeyState;
+
+    /// Time elapsed since an arbitrary fixed point, from a monotonic clock.
+    fn now(&self) -> Duration;
+
+    /// Blocks until `now()` reaches `deadline`.
+    ///
+    /// The default sleeps on the real clock. Hosts that block in `present`
+    /// (vsync) can return immediately; hosts with a virtual clock advance it.
+    fn wait_until(&mut self, deadline: Duration) {
+        let now = self.now();
+        if deadline > now {
+            spin_sleep(deadline - now);
+        }
+    }
+
+    /// Returns false once the user asked to quit (e.g. closed the window).
+    fn running(&self) -> bool {
+        true
+    }
+}
+
+/// Takes over running the `Cpu` from a `Machine`, e.g. to stop at
+/// breakpoints (see [`gdb`](crate::gdb)) or to count instructions (see
+/// [`coverage`](crate::coverage)).
+pub trait Debugger: Any {
+    /// Emulates one frame in place of `Cpu::run_frame`. A halted target
+    /// simply does not advance.
+    fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32);
+}
+
+/// A `Cpu` connected to a `Host`.
+pub struct Machine<H: Host> {
+    cpu: Cpu,
+    host: H,
+    /// Instructions per frame
+    ipf: u32,
+    scheduler: FrameScheduler,
+    beeping: bool,
+    debugger: Option<Box<dyn Debugger>>,
+}
+
+impl<H: Host> Machine<H> {
+    pub fn new(cpu: Cpu, host: H, ipf: u32) -> Machine<H> {
+        let scheduler = FrameScheduler::new(host.now());
+        Machine {
+            cpu,
+            host,
+            ipf,
+            scheduler,
+            beeping: false,
+            debugger: None,
+        }
+    }
+
+    /// Runs until the host stops running.
+    pub fn run(&mut self) {
+        while self.tick() {}
+    }
+
+    /// Waits for the next frame, then emulates the frames that are due and
+    /// presents the result. Returns false once the host stopped running.
+    pub fn tick(&mut self) -> bool {
+        self.host.wait_until(self.scheduler.deadline());
+        let due = self.scheduler.frames_due(self.host.now());
+
+        let keys = self.host.poll_input();
+        if !self.host.running() {
+            return false;
+        }
+        self.cpu.set_keys(keys);
+
+        for _ in 0..due {
+            match &mut self.debugger {
+                Some(debugger) => debugger.run_frame(&mut self.cpu, self.ipf),
+                None => self.cpu.run_frame(self.ipf),
+            }
+            self.host.audio_frame(self.cpu.buzzer());
+        }
+
+        let beeping = self.cpu.play();
+        if beeping != self.beeping {
+            self.host.beep(beeping);
+            self.beeping = beeping;
+        }
+
+        self.host.present(self.cpu.framebuffer());
+        self.cpu.take_dirty();
+
+        true
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+
+    pub fn host_mut(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    pub fn ipf(&self) -> u32 {
+        self.ipf
+    }
+
+    pub fn set_ipf(&mut self, ipf: u32) {
+        self.ipf = ipf;
+    }
+
+    /// Lets `debugger` run the CPU from the next frame on
+    pub fn set_debugger(&mut self, debugger: Option<Box<dyn Debugger>>) {
+        self.debugger = debugger;
+    }
+
+    /// The installed debugger, if it is a `T`
+    pub fn debugger_mut<T: Debugger>(&mut self) -> Option<&mut T> {
+        let debugger: &mut dyn Any = self.debugger.as_deref_mut()?;
+        debugger.downcast_mut()
+    }
+
+    pub fn into_parts(self) -> (Cpu, H) {
+        (self.cpu, self.host)
+    }
+}
+
+/// A host without display, sound or real time.
+///
+/// Its clock only advances when the machine waits, so every `Machine::tick`
+/// emulates exactly one frame, as fast as possible. Useful for tests, tools
+/// and benchmarks.
+#[derive(Debug, Default)]
+pub struct Headless {
+    now: Duration,
+    /// Keys reported by `poll_input`
+    pub keys: KeyState,
+    /// Number of `present` calls so far
+    pub frames: u64,
+    /// Whether the buzzer is on
+    pub beeping: bool,
+}
+
+impl Headless {
+    pub fn new() -> Headless {
+        Headless::default()
+    }
+}
+
+impl Host for Headless {
+    fn present(&mut self, _framebuffer: &Framebuffer) {
+        self.frames += 1;
+    }
+
+    fn beep(&mut self, on: bool) {
+        self.beeping = on;
+    }
+
+    fn poll_input(&mut self) -> KeyState {
+        self.keys
+    }
+
+    fn now(&self) -> Duration {
+        self.now
+    }
+
+    fn wait_until(&mut self, deadline: Duration) {
+        self.now = self.now.max(deadline);
+    }
+}
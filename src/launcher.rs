@@ -0,0 +1,282 @@
+//! The launcher shown when no ROM is given on the command line: a grid of
+//! the ROMs found in the ROM directory, titled from the ROM database. Each
+//! ROM is run headless for a few frames to get a preview of its display, and
+//! the selected one keeps running in its cell.
+
+use crate::bitmap::{Bitmap, CHAR_HEIGHT, CHAR_WIDTH};
+use crate::config::Config;
+use crate::Loaded;
+use r_chip_8::host::spin_sleep;
+use r_chip_8::romdb::Database;
+use r_chip_8::scheduler::FrameScheduler;
+use r_chip_8::{Cpu, Palette, Rgba};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use sdl2::EventPump;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+const BACKGROUND: Rgba = [0x10, 0x10, 0x10, 0xFF];
+const TEXT: Rgba = [0xC0, 0xC0, 0xC0, 0xFF];
+const DIM: Rgba = [0x50, 0x50, 0x50, 0xFF];
+const SELECTED: Rgba = [0x40, 0xE0, 0x40, 0xFF];
+
+/// Size of the launcher bitmap, scaled up to the window
+const WIDTH: usize = 320;
+const HEIGHT: usize = 160;
+
+/// Size of a preview, the low resolution display
+const PREVIEW_WIDTH: usize = 64;
+const PREVIEW_HEIGHT: usize = 32;
+/// Frames run headless before taking the preview
+const PREVIEW_FRAMES: u32 = 60;
+
+const COLUMNS: usize = 4;
+const CELL_WIDTH: usize = WIDTH / COLUMNS;
+const CELL_HEIGHT: usize = PREVIEW_HEIGHT + 4 + 2 * CHAR_HEIGHT;
+/// The header line above the grid
+const TOP: usize = CHAR_HEIGHT + 4;
+const ROWS: usize = (HEIGHT - TOP - CHAR_HEIGHT) / CELL_HEIGHT;
+
+/// What the user picked
+pub enum Choice {
+    Rom(String),
+    Quit,
+}
+
+struct Entry {
+    path: String,
+    title: String,
+    cpu: Cpu,
+    ipf: u32,
+    palette: Palette,
+}
+
+pub struct Launcher {
+    dir: String,
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl Launcher {
+    /// Lists the ROMs in `config.rom_dir` and its subdirectories. Files that
+    /// do not fit in memory are left out.
+    pub fn new(config: &Config, database: &Database) -> Launcher {
+        let mut paths = Vec::new();
+        find_files(Path::new(&config.rom_dir), &mut paths);
+        paths.sort();
+
+        let entries = paths
+            .into_iter()
+            .filter_map(|path| {
+                let Loaded {
+                    mut cpu,
+                    known,
+                    ipf,
+                    palette,
+                } = crate::load(config, database, &path).ok()?;
+                for _ in 0..PREVIEW_FRAMES {
+                    cpu.run_frame(ipf);
+                }
+                let title = match known {
+                    Some(rom) => rom.title,
+                    None => file_name(&path),
+                };
+                Some(Entry {
+                    path,
+                    title,
+                    cpu,
+                    ipf,
+                    palette,
+                })
+            })
+            .collect();
+
+        Launcher {
+            dir: config.rom_dir.clone(),
+            entries,
+            selected: 0,
+        }
+    }
+
+    /// Shows the launcher until a ROM is chosen, or a file dropped onto the
+    /// window
+    pub fn run(
+        &mut self,
+        canvas: &mut WindowCanvas,
+        event_pump: &mut EventPump,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) -> Choice {
+        canvas.window_mut().set_title("rCHIP-8").unwrap();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH as u32, HEIGHT as u32)
+            .unwrap();
+        let start = Instant::now();
+        let mut scheduler = FrameScheduler::new(start.elapsed());
+
+        loop {
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => return Choice::Quit,
+                    Event::DropFile { filename, .. } => return Choice::Rom(filename),
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(choice) = self.key(keycode) {
+                            return choice;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(entry) = self.entries.get_mut(self.selected) {
+                for _ in 0..scheduler.frames_due(start.elapsed()) {
+                    entry.cpu.run_frame(entry.ipf);
+                }
+            }
+            let bitmap = self.draw();
+            texture
+                .update(None, bitmap.rgba(), bitmap.width() * 4)
+                .unwrap();
+            canvas.copy(&texture, None, None).unwrap();
+            canvas.present();
+
+            let now = start.elapsed();
+            if scheduler.deadline() > now {
+                spin_sleep(scheduler.deadline() - now);
+            }
+        }
+    }
+
+    /// Moves the selection, or picks the selected ROM
+    fn key(&mut self, keycode: Keycode) -> Option<Choice> {
+        let last = self.entries.len().saturating_sub(1);
+        self.selected = match keycode {
+            Keycode::Left => self.selected.saturating_sub(1),
+            Keycode::Right => (self.selected + 1).min(last),
+            Keycode::Up => self.selected.saturating_sub(COLUMNS),
+            Keycode::Down => (self.selected + COLUMNS).min(last),
+            Keycode::Home => 0,
+            Keycode::End => last,
+            Keycode::Return | Keycode::Space => {
+                return self
+                    .entries
+                    .get(self.selected)
+                    .map(|entry| Choice::Rom(entry.path.clone()));
+            }
+            _ => self.selected,
+        };
+        None
+    }
+
+    fn draw(&self) -> Bitmap {
+        let mut bitmap = Bitmap::new(WIDTH, HEIGHT, BACKGROUND);
+        if self.entries.is_empty() {
+            bitmap.text(2, 2, &format!("No ROMs in {}", self.dir), TEXT);
+            bitmap.text(
+                2,
+                2 + CHAR_HEIGHT,
+                "Drop a ROM file here, or Esc to quit",
+                DIM,
+            );
+            return bitmap;
+        }
+        bitmap.text(
+            2,
+            2,
+            "Arrows: select   Return: run   Esc: quit   or drop a ROM file",
+            DIM,
+        );
+
+        // Scroll so that the selected row is the last one visible
+        let first_row = (self.selected / COLUMNS).saturating_sub(ROWS - 1);
+        let visible = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first_row * COLUMNS)
+            .take(ROWS * COLUMNS);
+        let max_chars = CELL_WIDTH / CHAR_WIDTH - 1;
+        for (index, entry) in visible {
+            let x = (index % COLUMNS) * CELL_WIDTH + (CELL_WIDTH - PREVIEW_WIDTH) / 2;
+            let y = TOP + (index / COLUMNS - first_row) * CELL_HEIGHT;
+            let color = if index == self.selected {
+                bitmap.fill_rect(
+                    x - 1,
+                    y - 1,
+                    PREVIEW_WIDTH + 2,
+                    PREVIEW_HEIGHT + 2,
+                    SELECTED,
+                );
+                SELECTED
+            } else {
+                TEXT
+            };
+            draw_preview(&mut bitmap, x, y, entry);
+
+            let title: String = entry.title.chars().take(max_chars).collect();
+            let title_x = (index % COLUMNS) * CELL_WIDTH
+                + (CELL_WIDTH - title.chars().count() * CHAR_WIDTH) / 2;
+            bitmap.text(title_x, y + PREVIEW_HEIGHT + 3, &title, color);
+        }
+
+        let selected = &self.entries[self.selected];
+        bitmap.text(2, HEIGHT - CHAR_HEIGHT, &selected.path, DIM);
+        bitmap
+    }
+}
+
+/// Draws the display of `entry` at 64x32, halving the SUPER-CHIP high
+/// resolution: a preview pixel is lit if any of the pixels it covers is.
+fn draw_preview(bitmap: &mut Bitmap, x: usize, y: usize, entry: &Entry) {
+    let framebuffer = entry.cpu.framebuffer();
+    let (cols, _) = framebuffer.resolution();
+    let step = cols / PREVIEW_WIDTH;
+    for py in 0..PREVIEW_HEIGHT {
+        for px in 0..PREVIEW_WIDTH {
+            let mut value = 0;
+            for dy in 0..step {
+                for dx in 0..step {
+                    value = value.max(framebuffer.pixel(px * step + dx, py * step + dy));
+                }
+            }
+            bitmap.fill_rect(x + px, y + py, 1, 1, entry.palette.colors[value as usize]);
+        }
+    }
+}
+
+/// Collects the files under `dir`, skipping hidden ones
+fn find_files(dir: &Path, paths: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            find_files(&path, paths);
+        } else {
+            paths.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
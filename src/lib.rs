@@ -0,0 +1,47 @@
+//! # r_chip_8
+//!
+//! `r_chip_8` is an interpreter for CHIP-8 programming language.
+//!
+//! This crate is the interpreter core only: it has no dependency on any
+//! windowing or audio library. A frontend implements [`Host`] and lets a
+//! [`Machine`] drive the loop, or loads a ROM into a [`Cpu`] and calls
+//! [`Cpu::run_frame`] 60 times per second itself (see [`scheduler`]). The SDL
+//! frontend in this repository is built with the `sdl` feature.
+
+/// Prints interpreter tracing to stdout when the `trace` feature is enabled.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "trace") {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod audio;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod cfg;
+pub mod coverage;
+pub mod cpu;
+pub mod decoder;
+pub mod display;
+pub mod gdb;
+pub mod host;
+pub mod memory;
+pub mod palette;
+pub mod profiler;
+pub mod quirks;
+pub mod rom;
+#[cfg(feature = "romdb")]
+pub mod romdb;
+pub mod scheduler;
+pub mod watch;
+
+pub use cpu::{Cpu, Engine, Fault, StackFrame};
+pub use decoder::Instruction;
+pub use display::{Framebuffer, Rgba};
+pub use host::{Debugger, Host, KeyState, Machine};
+pub use memory::Memory;
+pub use palette::Palette;
+pub use quirks::Quirks;
+pub use rom::RomInfo;
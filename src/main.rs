@@ -1,27 +1,76 @@
-mod cpu;
-
-// use rodio::source::{SineWave, Source};
-// use rodio::{Decoder, OutputStream, Sink};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use std::time::Duration;
+mod bitmap;
+mod config;
+mod debug_views;
+mod launcher;
+mod recording;
+mod renderer;
+mod sdl_host;
+
+use config::Config;
+use debug_views::DebugViews;
+use launcher::{Choice, Launcher};
+use r_chip_8::coverage::Coverage;
+use r_chip_8::gdb::GdbStub;
+use r_chip_8::profiler::Profiler;
+use r_chip_8::romdb::{Database, RomMetadata};
+use r_chip_8::{memory, Cpu, Machine, Palette};
+use recording::Capture;
+use renderer::Renderer;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use sdl2::{EventPump, VideoSubsystem};
+use sdl_host::{Exit, SdlHost, Sync};
+use std::fs;
+use std::io;
 
 const WIDTH: u32 = 640;
 const HEIGHT: u32 = 320;
-const SCALE_X: u32 = WIDTH / 64;
-const SCALE_Y: u32 = HEIGHT / 32;
-const CLOCK: u32 = 1_000_000; // Hz
 
-fn main() {
-    let mut cpu = cpu::Cpu::new();
+/// A ROM in a CPU set up for it
+pub struct Loaded {
+    pub cpu: Cpu,
+    pub known: Option<RomMetadata>,
+    pub ipf: u32,
+    pub palette: Palette,
+}
 
+/// Loads `rom` with the settings from the command line, the configuration
+/// file and the ROM database, in that order of precedence
+pub fn load(config: &Config, database: &Database, rom: &str) -> io::Result<Loaded> {
+    let new_cpu = |start: u16| -> io::Result<Cpu> {
+        let mut cpu = Cpu::new();
+        cpu.set_protection(config.protection);
+        cpu.set_start(start)?;
+        Ok(cpu)
+    };
+    let mut cpu = new_cpu(config.start.unwrap_or(memory::PROGRAM.start))?;
+    let info = cpu.load_rom(rom)?;
+    let known = database.lookup(&info.sha1);
+    if let (None, Some(start)) = (
+        config.start,
+        known.as_ref().and_then(|rom| rom.start_address),
+    ) {
+        // The start address decides where the ROM goes, so load it again
+        if start != cpu.pc() {
+            cpu = new_cpu(start)?;
+            cpu.load_rom(rom)?;
+        }
+    }
+    cpu.set_quirks(config.quirks(known.as_ref()));
+    Ok(Loaded {
+        cpu,
+        ipf: config.ipf(known.as_ref()),
+        palette: config.palette(rom, known.as_ref()),
+        known,
+    })
+}
 
-    // cpu.load_rom("rom/test_opcode.ch8")
-    //     .expect("Error reading rom");
-    cpu.load_rom("rom/c8games/SYZYGY")
-        .expect("Error reading rom");
+fn main() {
+    let config = Config::from_args();
+    let database = config.database().unwrap_or_else(|e| {
+        eprintln!("Cannot read the ROM database: {}", e);
+        std::process::exit(1);
+    });
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -31,110 +80,142 @@ fn main() {
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    let mut canvas = match config.sync {
+        Sync::Vsync => window.into_canvas().present_vsync().build().unwrap(),
+        Sync::Timer => window.into_canvas().build().unwrap(),
+    };
     let mut event_pump = sdl_context.event_pump().unwrap();
-
-    // let mut speaker = adi::speaker
-
-    cpu.start_timers();
-
-    'running: loop {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => {
-                    break 'running;
+    let texture_creator = canvas.texture_creator();
+
+    let mut launcher = match config.rom {
+        Some(_) => None,
+        None => Some(Launcher::new(&config, &database)),
+    };
+    let mut next = config.rom.clone();
+    loop {
+        let rom = match (next.take(), &mut launcher) {
+            (Some(rom), _) => rom,
+            (None, Some(launcher)) => {
+                match launcher.run(&mut canvas, &mut event_pump, &texture_creator) {
+                    Choice::Rom(rom) => rom,
+                    Choice::Quit => break,
                 }
-                Event::KeyDown { keycode, .. } => match keycode {
-                    Some(Keycode::Escape) => break 'running,
-                    Some(Keycode::Num1) => cpu.key_press(0x1),
-                    Some(Keycode::Num2) => cpu.key_press(0x2),
-                    Some(Keycode::Num3) => cpu.key_press(0x3),
-                    Some(Keycode::Num4) => cpu.key_press(0xC),
-
-                    Some(Keycode::Q) => cpu.key_press(0x4),
-                    Some(Keycode::W) => cpu.key_press(0x5),
-                    Some(Keycode::E) => cpu.key_press(0x6),
-                    Some(Keycode::R) => cpu.key_press(0xD),
-
-                    Some(Keycode::A) => cpu.key_press(0x7),
-                    Some(Keycode::S) => cpu.key_press(0x8),
-                    Some(Keycode::D) => cpu.key_press(0x9),
-                    Some(Keycode::F) => cpu.key_press(0xE),
-
-                    Some(Keycode::Z) => cpu.key_press(0xA),
-                    Some(Keycode::X) => cpu.key_press(0x0),
-                    Some(Keycode::C) => cpu.key_press(0xB),
-                    Some(Keycode::V) => cpu.key_press(0xF),
-                    _ => {}
-                },
-                Event::KeyUp { keycode, .. } => match keycode {
-                    Some(Keycode::Escape) => break 'running,
-                    Some(Keycode::Num1) => cpu.key_release(0x1),
-                    Some(Keycode::Num2) => cpu.key_release(0x2),
-                    Some(Keycode::Num3) => cpu.key_release(0x3),
-                    Some(Keycode::Num4) => cpu.key_release(0xC),
-
-                    Some(Keycode::Q) => cpu.key_release(0x4),
-                    Some(Keycode::W) => cpu.key_release(0x5),
-                    Some(Keycode::E) => cpu.key_release(0x6),
-                    Some(Keycode::R) => cpu.key_release(0xD),
-
-                    Some(Keycode::A) => cpu.key_release(0x7),
-                    Some(Keycode::S) => cpu.key_release(0x8),
-                    Some(Keycode::D) => cpu.key_release(0x9),
-                    Some(Keycode::F) => cpu.key_release(0xE),
-
-                    Some(Keycode::Z) => cpu.key_release(0xA),
-                    Some(Keycode::X) => cpu.key_release(0x0),
-                    Some(Keycode::C) => cpu.key_release(0xB),
-                    Some(Keycode::V) => cpu.key_release(0xF),
-                    _ => {}
-                },
-                _ => {}
             }
+            (None, None) => break,
+        };
+        let loaded = match load(&config, &database, &rom) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}: {}", rom, e);
+                if launcher.is_none() {
+                    std::process::exit(1);
+                }
+                continue;
+            }
+        };
+
+        let exit;
+        (canvas, event_pump, exit) = run(
+            &config,
+            &video_subsystem,
+            canvas,
+            event_pump,
+            &texture_creator,
+            &rom,
+            loaded,
+        );
+        match exit {
+            Exit::Quit => break,
+            Exit::Back => {}
+            Exit::Load(path) => next = Some(path),
         }
+    }
+}
 
-        cpu.run();
-
-        canvas.set_draw_color(Color::GREEN);
+/// Runs a ROM in the window until the user leaves it
+fn run(
+    config: &Config,
+    video_subsystem: &VideoSubsystem,
+    mut canvas: WindowCanvas,
+    event_pump: EventPump,
+    texture_creator: &TextureCreator<WindowContext>,
+    rom: &str,
+    loaded: Loaded,
+) -> (WindowCanvas, EventPump, Exit) {
+    let Loaded {
+        cpu,
+        known,
+        ipf,
+        palette,
+    } = loaded;
+    let title = match &known {
+        Some(known) => {
+            println!("{}: {}", rom, known.title);
+            format!("rCHIP-8 - {}", known.title)
+        }
+        None => format!("rCHIP-8 - {}", rom),
+    };
+    canvas.window_mut().set_title(&title).unwrap();
+
+    let renderer = Renderer::new(texture_creator, cpu.framebuffer(), palette);
+    let capture = Capture::new(
+        palette,
+        config.capture_scale,
+        config.record.as_deref(),
+        config.record_audio.as_deref(),
+    );
+    let mut host = SdlHost::new(canvas, event_pump, renderer, config.sync, capture);
+    if let Some(rom) = &known {
+        host.set_game_keys(&rom.keys);
+    }
 
-        if cpu.draw() {
-            for row in 0..32 {
-                for col in 0..64 {
-                    if cpu.vram(col as usize, row as usize) != 0 {
-                        let rect = Rect::new(
-                            (col * SCALE_X) as i32,
-                            (row * SCALE_Y) as i32,
-                            SCALE_X,
-                            SCALE_Y,
-                        );
-                        canvas.fill_rect(rect).unwrap();
-                    }
-                }
+    let mut debug_views = DebugViews::new(video_subsystem.clone());
+    let mut machine = Machine::new(cpu, host, ipf);
+    if let Some(port) = config.gdb {
+        println!("Waiting for GDB on localhost:{}", port);
+        match GdbStub::listen(("127.0.0.1", port)) {
+            Ok(stub) => machine.set_debugger(Some(Box::new(stub))),
+            Err(e) => {
+                eprintln!("Cannot start the GDB stub: {}", e);
+                std::process::exit(1);
             }
         }
+    }
+    if config.coverage.is_some() {
+        machine.set_debugger(Some(Box::new(Coverage::new())));
+    }
+    if config.profile.is_some() {
+        machine.set_debugger(Some(Box::new(Profiler::new())));
+    }
+    while machine.tick() {
+        for event in machine.host_mut().take_debug_events() {
+            debug_views.handle(event, machine.cpu_mut());
+        }
+        debug_views.update(machine.cpu_mut());
+    }
 
-        if cpu.play() {
-            // music.play(1).unwrap();
-            println!("BEEP BOP");
-            // let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-            // let sink = rodio::Sink::try_new(&stream_handle).unwrap();
-            //
-            // // Add a dummy source of the sake of the example.
-            // let source = SineWave::new(440.0)
-            //     .take_duration(Duration::from_secs_f32(0.2))
-            //     .amplify(0.20);
-            // sink.append(source);
-            //
-            // // The sound plays in a separate thread. This call will block the current thread until the sink
-            // // has finished playing all its queued sounds.
-            // sink.sleep_until_end();
-
+    if let Some(path) = &config.coverage {
+        let start = machine.cpu().start();
+        let coverage = machine.debugger_mut::<Coverage>().unwrap();
+        if let Err(e) = fs::read(rom).and_then(|bytes| coverage.save(path, &bytes, start)) {
+            eprintln!("Cannot write the coverage to {}: {}", path, e);
         }
+    }
+    if let Some(path) = &config.profile {
+        let profiler = std::mem::take(machine.debugger_mut::<Profiler>().unwrap());
+        if let Err(e) = profiler.save(path, machine.cpu().memory()) {
+            eprintln!("Cannot write the profile to {}: {}", path, e);
+        }
+    }
 
-        canvas.present();
-        ::std::thread::sleep(Duration::from_micros(1_000_000_000 / CLOCK as u64));
+    let (cpu, host) = machine.into_parts();
+    let (canvas, event_pump, mut capture, exit) = host.into_parts();
+    if let Some(fault) = cpu.fault() {
+        eprintln!("Interpreter stopped: {}", fault);
+    }
+    if let Some(path) = &config.screenshot {
+        capture.screenshot(cpu.framebuffer(), path);
     }
+    capture.finish();
+    (canvas, event_pump, exit)
 }
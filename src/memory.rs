@@ -0,0 +1,307 @@
+//! The 4 KiB address space and its layout.
+//!
+//! ```text
+//! 0x000 +-------------------+
+//!       | interpreter       |  reserved on the original hardware
+//! 0x050 |   font (0x50 B)   |
+//! 0x0A0 |                   |
+//! 0x200 +-------------------+
+//!       | program           |  ROMs are loaded and start here...
+//! 0x600 |                   |  ...or here on the ETI-660
+//!       |                   |
+//! 0xFFF +-------------------+
+//! ```
+//!
+//! Accesses made by the program go through [`Memory::read_as`],
+//! [`Memory::read_chunk_as`] and [`Memory::write`], which report them to the
+//! installed [`MemoryHook`]s (see [`watch`](crate::watch)). Without a hook the
+//! only cost is one predictable branch per access. `read` and `read_chunk`
+//! are for debuggers and do not notify the hooks.
+
+use std::any::Any;
+use std::fmt;
+
+/// Size of the address space
+pub const SIZE: usize = 4096;
+
+/// A named, half-open range of addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Region {
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address < self.end
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Where the interpreter itself lived on the COSMAC VIP. Programs should not
+/// write here.
+pub const INTERPRETER: Region = Region {
+    name: "interpreter",
+    start: 0x000,
+    end: 0x200,
+};
+
+/// The built-in hexadecimal font, inside the interpreter region
+pub const FONT: Region = Region {
+    name: "font",
+    start: 0x050,
+    end: 0x0A0,
+};
+
+/// Programs loaded at the usual start address
+pub const PROGRAM: Region = Region {
+    name: "program",
+    start: 0x200,
+    end: SIZE as u16,
+};
+
+/// Programs for the ETI-660, which start at 0x600
+pub const ETI_660_PROGRAM: Region = Region {
+    name: "ETI-660 program",
+    start: 0x600,
+    end: SIZE as u16,
+};
+
+/// What happens when a program writes to the interpreter region
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protection {
+    /// The write goes through, as on most interpreters
+    #[default]
+    Off,
+    /// The write is silently dropped
+    Ignore,
+    /// The write is dropped and reported as a `WriteFault`
+    Fault,
+}
+
+impl Protection {
+    /// Names accepted by `Protection::from_name`.
+    pub const NAMES: [&'static str; 3] = ["off", "ignore", "fault"];
+
+    pub fn from_name(name: &str) -> Option<Protection> {
+        match name {
+            "off" => Some(Protection::Off),
+            "ignore" => Some(Protection::Ignore),
+            "fault" => Some(Protection::Fault),
+            _ => None,
+        }
+    }
+}
+
+/// How the program accessed a byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    /// Data read by Fx65, or sprite data read by DRW
+    Read,
+    /// Written by Fx33 or Fx55
+    Write,
+    /// Fetched as part of an instruction
+    Execute,
+}
+
+impl Access {
+    pub const ALL: [Access; 3] = [Access::Read, Access::Write, Access::Execute];
+}
+
+/// Observes the accesses the program makes to memory.
+pub trait MemoryHook: Any + Send {
+    /// Called for every byte accessed. `value` is the byte read, or the byte
+    /// the program tried to write (even if the protection dropped it).
+    fn access(&mut self, access: Access, address: u16, value: u8);
+}
+
+/// Both hooks see every access
+impl<A: MemoryHook, B: MemoryHook> MemoryHook for (A, B) {
+    fn access(&mut self, access: Access, address: u16, value: u8) {
+        self.0.access(access, address, value);
+        self.1.access(access, address, value);
+    }
+}
+
+/// A write to the protected interpreter region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteFault {
+    pub address: u16,
+}
+
+impl fmt::Display for WriteFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write to protected address {:03X} ({} region)",
+            self.address,
+            region_of(self.address).name
+        )
+    }
+}
+
+/// The most specific named region containing `address`
+pub fn region_of(address: u16) -> Region {
+    [FONT, INTERPRETER]
+        .into_iter()
+        .find(|region| region.contains(address))
+        .unwrap_or(PROGRAM)
+}
+
+pub struct Memory {
+    data: Vec<u8>,
+    protection: Protection,
+    /// In the order they were added
+    hooks: Vec<Box<dyn MemoryHook>>,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        let data = vec![0; SIZE];
+        Memory {
+            data,
+            protection: Protection::Off,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Installs `hook` as the only hook, replacing every installed hook and
+    /// returning the one added last
+    pub fn set_hook(&mut self, hook: Box<dyn MemoryHook>) -> Option<Box<dyn MemoryHook>> {
+        let previous = self.take_hook();
+        self.hooks = vec![hook];
+        previous
+    }
+
+    /// Removes every hook, returning the one added last
+    pub fn take_hook(&mut self) -> Option<Box<dyn MemoryHook>> {
+        std::mem::take(&mut self.hooks).pop()
+    }
+
+    /// Installs `hook` next to the installed hooks, so that tools which
+    /// observe memory independently (a debugger and a debug view) can be
+    /// active at the same time
+    pub fn add_hook(&mut self, hook: Box<dyn MemoryHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Removes and returns the first installed hook that is a `T`
+    pub fn remove_hook<T: MemoryHook>(&mut self) -> Option<Box<T>> {
+        let index = self
+            .hooks
+            .iter()
+            .position(|hook| (hook.as_ref() as &dyn Any).is::<T>())?;
+        let hook: Box<dyn Any> = self.hooks.remove(index);
+        hook.downcast().ok()
+    }
+
+    pub fn has_hook(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    /// The first installed hook that is a `T`
+    pub fn hook_mut<T: MemoryHook>(&mut self) -> Option<&mut T> {
+        self.hooks.iter_mut().find_map(|hook| {
+            let hook: &mut dyn Any = hook.as_mut();
+            hook.downcast_mut()
+        })
+    }
+
+    #[inline]
+    fn notify(&mut self, access: Access, offset: usize, value: u8) {
+        for hook in &mut self.hooks {
+            hook.access(access, offset as u16, value);
+        }
+    }
+
+    pub fn protection(&self) -> Protection {
+        self.protection
+    }
+
+    pub fn set_protection(&mut self, protection: Protection) {
+        self.protection = protection;
+    }
+
+    /// Copies `data` to `offset` regardless of the protection, for the
+    /// interpreter's own use (font, ROM).
+    pub fn load(&mut self, offset: usize, data: &[u8]) {
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Writes `data` from `offset` on, as a program would. Protected bytes are
+    /// skipped; the first one is reported with `Protection::Fault`.
+    pub fn write_vec(&mut self, offset: usize, data: Vec<u8>) -> Result<(), WriteFault> {
+        let mut result = Ok(());
+        for (i, byte) in data.into_iter().enumerate() {
+            result = result.and(self.write(offset + i, byte));
+        }
+        result
+    }
+
+    /// Writes a byte as a program would, subject to the protection.
+    pub fn write(&mut self, offset: usize, data: u8) -> Result<(), WriteFault> {
+        self.notify(Access::Write, offset, data);
+        if self.protection != Protection::Off && INTERPRETER.contains(offset as u16) {
+            return match self.protection {
+                Protection::Fault => Err(WriteFault {
+                    address: offset as u16,
+                }),
+                _ => Ok(()),
+            };
+        }
+        self.data[offset] = data;
+        Ok(())
+    }
+
+    /// Reads a byte on behalf of the program
+    #[inline]
+    pub fn read_as(&mut self, access: Access, offset: usize) -> u8 {
+        let value = self.data[offset];
+        self.notify(access, offset, value);
+        value
+    }
+
+    /// Reads `n` bytes on behalf of the program
+    #[inline]
+    pub fn read_chunk_as(&mut self, access: Access, offset: usize, n: usize) -> &[u8] {
+        for hook in &mut self.hooks {
+            for (i, value) in self.data[offset..offset + n].iter().enumerate() {
+                hook.access(access, (offset + i) as u16, *value);
+            }
+        }
+        &self.data[offset..offset + n]
+    }
+
+    /// Reads a byte without notifying the hook
+    pub fn read(&self, offset: usize) -> u8 {
+        self.data[offset]
+    }
+
+    /// Reads `n` bytes without notifying the hook
+    pub fn read_chunk(&self, offset: usize, n: usize) -> &[u8] {
+        &self.data[offset..offset + n]
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Memory")
+            .field("protection", &self.protection)
+            .field("hooks", &self.hooks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
+    }
+}
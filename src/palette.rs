@@ -0,0 +1,102 @@
+//! Colour palettes for rendering the framebuffer.
+
+use crate::display::{Rgba, COLORS};
+
+/// Colours for every pixel value: index 0 is the background, index 1 the
+/// foreground of plain CHIP-8 and SUPER-CHIP games. Indexes 2 and 3 are only
+/// reachable with XO-CHIP's second plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgba; COLORS],
+}
+
+impl Palette {
+    /// Green phosphor on black
+    pub const CLASSIC: Palette = Palette {
+        colors: [
+            [0x00, 0x00, 0x00, 0xFF],
+            [0x00, 0xFF, 0x00, 0xFF],
+            [0x00, 0x80, 0x00, 0xFF],
+            [0x00, 0xFF, 0x00, 0xFF],
+        ],
+    };
+
+    /// Amber monochrome monitor
+    pub const AMBER: Palette = Palette {
+        colors: [
+            [0x1A, 0x0F, 0x00, 0xFF],
+            [0xFF, 0xB0, 0x00, 0xFF],
+            [0x80, 0x58, 0x00, 0xFF],
+            [0xFF, 0xCC, 0x40, 0xFF],
+        ],
+    };
+
+    /// Four shades of a green handheld LCD
+    pub const LCD: Palette = Palette {
+        colors: [
+            [0x9B, 0xBC, 0x0F, 0xFF],
+            [0x0F, 0x38, 0x0F, 0xFF],
+            [0x8B, 0xAC, 0x0F, 0xFF],
+            [0x30, 0x62, 0x30, 0xFF],
+        ],
+    };
+
+    /// White on black
+    pub const HIGH_CONTRAST: Palette = Palette {
+        colors: [
+            [0x00, 0x00, 0x00, 0xFF],
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0xFF, 0xFF, 0x00, 0xFF],
+            [0x00, 0xFF, 0xFF, 0xFF],
+        ],
+    };
+
+    /// Octo's default XO-CHIP colours
+    pub const XO: Palette = Palette {
+        colors: [
+            [0x99, 0x66, 0x00, 0xFF],
+            [0xFF, 0xCC, 0x00, 0xFF],
+            [0xFF, 0x66, 0x00, 0xFF],
+            [0x66, 0x22, 0x00, 0xFF],
+        ],
+    };
+
+    /// Names accepted by `Palette::from_name`.
+    pub const NAMES: [&'static str; 5] = ["classic", "amber", "lcd", "high-contrast", "xo"];
+
+    /// Looks up a palette by name.
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name {
+            "classic" => Some(Palette::CLASSIC),
+            "amber" => Some(Palette::AMBER),
+            "lcd" => Some(Palette::LCD),
+            "high-contrast" => Some(Palette::HIGH_CONTRAST),
+            "xo" => Some(Palette::XO),
+            _ => None,
+        }
+    }
+
+    pub fn set_background(&mut self, color: Rgba) {
+        self.colors[0] = color;
+    }
+
+    pub fn set_foreground(&mut self, color: Rgba) {
+        self.colors[1] = color;
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::CLASSIC
+    }
+}
+
+/// Parses a colour written as `RRGGBB`, optionally prefixed by `#`.
+pub fn parse_color(s: &str) -> Option<Rgba> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8, 0xFF])
+}
@@ -0,0 +1,286 @@
+//! An instruction-level profiler: where a ROM spends its instructions.
+//!
+//! [`Profiler`] steps the CPU itself, like `coverage::Coverage`, and counts
+//!
+//! - every executed address,
+//! - every subroutine, inclusive (with the subroutines it calls) and
+//!   exclusive, following the CPU's stack,
+//! - the instructions spent in delay timer wait loops: `LD Vx, DT`, then
+//!   `SE Vx, kk` and a `JP` back to the `LD`.
+//!
+//! [`Profiler::report`] summarises the counts as text, and
+//! [`Profiler::folded`] writes them as folded stacks, the input of
+//! flamegraph tools such as `inferno-flamegraph` or `flamegraph.pl`. Code
+//! outside any subroutine is `main`, subroutines are `sub_XXX` and wait loops
+//! are an extra `dt_wait` frame.
+
+use crate::decoder::Instruction;
+use crate::host::Debugger;
+use crate::memory::{Memory, SIZE};
+use crate::Cpu;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+
+/// Hot addresses listed by `report`
+const HOT_SPOTS: usize = 20;
+
+/// Counts of a subroutine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    pub calls: u64,
+    /// Instructions executed in it and the subroutines it called
+    pub inclusive: u64,
+    /// Instructions executed in it only
+    pub exclusive: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    counts: Vec<u64>,
+    total: u64,
+    dt_wait: u64,
+    subroutines: BTreeMap<u16, Subroutine>,
+    /// The subroutines being executed with their return addresses,
+    /// outermost first, as on the CPU's stack
+    calls: Vec<(u16, u16)>,
+    /// Instructions by call stack, with whether they were in a wait loop
+    stacks: HashMap<(Vec<u16>, bool), u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            counts: vec![0; SIZE],
+            total: 0,
+            dt_wait: 0,
+            subroutines: BTreeMap::new(),
+            calls: Vec::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    /// How often the instruction at `address` was executed
+    pub fn count(&self, address: u16) -> u64 {
+        self.counts[address as usize]
+    }
+
+    /// Instructions executed in total
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Instructions executed in delay timer wait loops
+    pub fn dt_wait(&self) -> u64 {
+        self.dt_wait
+    }
+
+    /// The subroutines called, by address
+    pub fn subroutines(&self) -> &BTreeMap<u16, Subroutine> {
+        &self.subroutines
+    }
+
+    /// Executes and counts a single instruction
+    pub fn step(&mut self, cpu: &mut Cpu) {
+        if cpu.fault().is_some() {
+            return;
+        }
+        let pc = cpu.pc();
+        let instruction = decode(cpu.memory(), pc);
+        let waiting = in_dt_wait_loop(cpu.memory(), pc);
+        cpu.step();
+
+        self.counts[pc as usize] += 1;
+        self.total += 1;
+        if waiting {
+            self.dt_wait += 1;
+        }
+        let calls: Vec<u16> = self.calls.iter().map(|&(address, _)| address).collect();
+        for (depth, address) in calls.iter().enumerate() {
+            let subroutine = self.subroutines.entry(*address).or_default();
+            // Count recursive subroutines once
+            if !calls[..depth].contains(address) {
+                subroutine.inclusive += 1;
+            }
+        }
+        if let Some(innermost) = calls.last() {
+            self.subroutines.entry(*innermost).or_default().exclusive += 1;
+        }
+        *self.stacks.entry((calls, waiting)).or_default() += 1;
+
+        if cpu.fault().is_some() {
+            return;
+        }
+        match instruction {
+            Instruction::Call(target) => {
+                self.subroutines.entry(target).or_default().calls += 1;
+                self.follow_stack(cpu, Some(target));
+            }
+            Instruction::Ret => self.follow_stack(cpu, None),
+            _ => {}
+        }
+    }
+
+    /// Updates `calls` after a CALL to `target`, or a RET. The stack does not
+    /// simply grow and shrink by one under `StackPolicy::Wrap` or `Ignore`,
+    /// so the frames are matched with the CPU's return addresses.
+    fn follow_stack(&mut self, cpu: &Cpu, target: Option<u16>) {
+        let stack = cpu.stack();
+        let kept = self
+            .calls
+            .iter()
+            .zip(stack)
+            .take_while(|((_, return_address), address)| return_address == *address)
+            .count();
+        self.calls.truncate(kept);
+        // Pushed by the CALL, or stale entries a wrapping RET exposed, which
+        // are named after the CALL at their call site
+        for frame in cpu.backtrace().iter().rev().skip(kept) {
+            let address = target.or(frame.target).unwrap_or(frame.call_site);
+            self.calls.push((address, frame.return_address));
+        }
+    }
+
+    /// Like `Cpu::run_frame`, counting every instruction
+    pub fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        for _ in 0..ipf {
+            self.step(cpu);
+            if cpu.in_vblank_wait() {
+                break;
+            }
+        }
+        cpu.end_frame();
+    }
+
+    /// The hottest addresses, the subroutines by inclusive count and the
+    /// share of the wait loops, disassembling from `memory`
+    pub fn report(&self, memory: &Memory) -> String {
+        let percent = |count: u64| match self.total {
+            0 => 0.0,
+            total => 100.0 * count as f64 / total as f64,
+        };
+        let mut report = format!("{} instructions executed\n", self.total);
+        writeln!(
+            report,
+            "{} ({:.1}%) in delay timer wait loops",
+            self.dt_wait,
+            percent(self.dt_wait)
+        )
+        .unwrap();
+
+        let mut hot: Vec<u16> = (0..SIZE as u16)
+            .filter(|&address| self.count(address) > 0)
+            .collect();
+        hot.sort_by_key(|&address| std::cmp::Reverse(self.count(address)));
+        writeln!(report, "\nHot spots\n     count       %  addr  instruction").unwrap();
+        for &address in hot.iter().take(HOT_SPOTS) {
+            let count = self.count(address);
+            let wait = if in_dt_wait_loop(memory, address) {
+                "  (DT wait)"
+            } else {
+                ""
+            };
+            writeln!(
+                report,
+                "{:>10}  {:5.1}%  {:03X}  {}{}",
+                count,
+                percent(count),
+                address,
+                decode(memory, address),
+                wait
+            )
+            .unwrap();
+        }
+
+        let mut subroutines: Vec<(&u16, &Subroutine)> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(_, subroutine)| std::cmp::Reverse(subroutine.inclusive));
+        writeln!(
+            report,
+            "\nSubroutines\n  addr      calls   inclusive       %   exclusive       %"
+        )
+        .unwrap();
+        for (address, subroutine) in subroutines {
+            writeln!(
+                report,
+                "  {:03X} {:>10}  {:>10}  {:5.1}%  {:>10}  {:5.1}%",
+                address,
+                subroutine.calls,
+                subroutine.inclusive,
+                percent(subroutine.inclusive),
+                subroutine.exclusive,
+                percent(subroutine.exclusive)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// The counts as folded stacks, one `main;sub_XXX;... count` line per
+    /// call stack
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|((calls, waiting), count)| {
+                let mut line = String::from("main");
+                for address in calls {
+                    write!(line, ";sub_{:03X}", address).unwrap();
+                }
+                if *waiting {
+                    line.push_str(";dt_wait");
+                }
+                format!("{} {}", line, count)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Saves the report to `path` and the folded stacks to `path` with
+    /// `.folded` appended
+    pub fn save(&self, path: &str, memory: &Memory) -> io::Result<()> {
+        fs::write(path, self.report(memory))?;
+        fs::write(format!("{}.folded", path), self.folded())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+impl Debugger for Profiler {
+    fn run_frame(&mut self, cpu: &mut Cpu, ipf: u32) {
+        Profiler::run_frame(self, cpu, ipf);
+    }
+}
+
+fn decode(memory: &Memory, address: u16) -> Instruction {
+    let address = address as usize;
+    Instruction::decode(
+        (memory.read(address) as u16) << 8 | memory.read((address + 1) % SIZE) as u16,
+    )
+}
+
+/// Whether `address` is one of the three instructions of a delay timer wait
+/// loop: `LD Vx, DT`, `SE Vx, kk`, `JP` to the `LD`
+fn in_dt_wait_loop(memory: &Memory, address: u16) -> bool {
+    (0..3).any(|i| {
+        let start = match address.checked_sub(2 * i) {
+            Some(start) if start as usize + 5 < SIZE => start,
+            _ => return false,
+        };
+        match (
+            decode(memory, start),
+            decode(memory, start + 2),
+            decode(memory, start + 4),
+        ) {
+            (Instruction::LdVxDt(x), Instruction::SeByte(y, _), Instruction::Jp(target)) => {
+                x == y && target == start
+            }
+            _ => false,
+        }
+    })
+}
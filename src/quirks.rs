@@ -0,0 +1,81 @@
+/// What CALL does when the stack is full, and RET when it is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackPolicy {
+    /// The interpreter stops with a `Fault`
+    Error,
+    /// The stack pointer wraps around: CALL overwrites the oldest return
+    /// address and RET returns to a stale one, like a hardware stack
+    Wrap,
+    /// CALL jumps without saving the return address; RET does nothing
+    Ignore,
+}
+
+/// Behaviours that differ between CHIP-8 interpreters.
+///
+/// Games are usually written against one specific interpreter and rely on its
+/// quirks, so the profile has to match the platform the ROM was made for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// Dxyn waits for the next vertical blank before returning, which ends
+    /// the current frame. On the COSMAC VIP this limits sprite draws to 60 per
+    /// second.
+    pub display_wait: bool,
+    /// Sprite pixels past the right or bottom edge of the display wrap around to
+    /// the opposite side instead of being clipped.
+    pub wrap_sprites: bool,
+    /// In high resolution mode DRW sets VF to the number of sprite rows that
+    /// collided or were clipped off the bottom, instead of 0 or 1.
+    pub collision_rows: bool,
+    /// Number of return addresses the stack holds
+    pub stack_depth: usize,
+    /// What happens when the stack overflows or underflows
+    pub stack_policy: StackPolicy,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const VIP: Quirks = Quirks {
+        display_wait: true,
+        wrap_sprites: false,
+        collision_rows: false,
+        stack_depth: 12,
+        stack_policy: StackPolicy::Wrap,
+    };
+
+    /// SUPER-CHIP 1.1 on the HP-48.
+    pub const SCHIP: Quirks = Quirks {
+        display_wait: false,
+        wrap_sprites: false,
+        collision_rows: true,
+        stack_depth: 16,
+        stack_policy: StackPolicy::Error,
+    };
+
+    /// What most modern interpreters (and test ROMs) expect.
+    pub const MODERN: Quirks = Quirks {
+        display_wait: false,
+        wrap_sprites: true,
+        collision_rows: false,
+        stack_depth: 16,
+        stack_policy: StackPolicy::Error,
+    };
+
+    /// Names accepted by `Quirks::from_name`.
+    pub const NAMES: [&'static str; 3] = ["vip", "schip", "modern"];
+
+    /// Looks up a profile by name.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" | "chip8" => Some(Quirks::VIP),
+            "schip" => Some(Quirks::SCHIP),
+            "modern" => Some(Quirks::MODERN),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::MODERN
+    }
+}
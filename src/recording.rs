@@ -0,0 +1,93 @@
+//! Screenshots and recordings taken by the SDL frontend.
+
+use r_chip_8::audio::AudioRecorder;
+use r_chip_8::capture::{self, Recorder};
+use r_chip_8::{Framebuffer, Palette};
+use std::path::Path;
+use std::time::Duration;
+
+pub struct Capture {
+    palette: Palette,
+    scale: usize,
+    /// Set by the screenshot hotkey, handled at the next `present`
+    screenshot_requested: bool,
+    recorder: Option<Recorder>,
+    /// Output file and the audio recorded so far
+    audio: Option<(String, AudioRecorder)>,
+}
+
+impl Capture {
+    pub fn new(
+        palette: Palette,
+        scale: usize,
+        record: Option<&str>,
+        record_audio: Option<&str>,
+    ) -> Capture {
+        let recorder = record.and_then(|path| match Recorder::new(path, palette, scale) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                eprintln!("Not recording: {}", e);
+                None
+            }
+        });
+        Capture {
+            palette,
+            scale,
+            screenshot_requested: false,
+            recorder,
+            audio: record_audio.map(|path| (path.to_string(), AudioRecorder::default())),
+        }
+    }
+
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    /// Called with every presented frame
+    pub fn frame(&mut self, framebuffer: &Framebuffer, now: Duration) {
+        if std::mem::take(&mut self.screenshot_requested) {
+            let path = next_screenshot_path();
+            self.screenshot(framebuffer, &path);
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.push(framebuffer, now);
+        }
+    }
+
+    /// Called after every emulated frame
+    pub fn audio_frame(&mut self, buzzer: bool) {
+        if let Some((_, audio)) = &mut self.audio {
+            audio.frame(buzzer);
+        }
+    }
+
+    pub fn screenshot(&self, framebuffer: &Framebuffer, path: &str) {
+        match capture::save_png(path, framebuffer, &self.palette, self.scale) {
+            Ok(()) => println!("Saved screenshot to {}", path),
+            Err(e) => eprintln!("Cannot save screenshot to {}: {}", path, e),
+        }
+    }
+
+    /// Writes the recordings, if any
+    pub fn finish(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish() {
+                eprintln!("Cannot save recording: {}", e);
+            }
+        }
+        if let Some((path, audio)) = self.audio.take() {
+            if let Err(e) = audio.save_wav(&path) {
+                eprintln!("Cannot save audio to {}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// The first of `screenshot-1.png`, `screenshot-2.png`, ... that does not
+/// exist yet
+fn next_screenshot_path() -> String {
+    (1..)
+        .map(|n| format!("screenshot-{}.png", n))
+        .find(|path| !Path::new(path).exists())
+        .unwrap()
+}
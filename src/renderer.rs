@@ -0,0 +1,57 @@
+//! Drawing the framebuffer onto the SDL window.
+
+use r_chip_8::{Framebuffer, Palette};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+/// Keeps a streaming texture with the framebuffer contents and stretches it
+/// over the whole window.
+pub struct Renderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture: Texture<'a>,
+    palette: Palette,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        framebuffer: &Framebuffer,
+        palette: Palette,
+    ) -> Renderer<'a> {
+        let (cols, rows) = framebuffer.resolution();
+        let texture = Self::create_texture(texture_creator, cols, rows);
+        Renderer {
+            texture_creator,
+            texture,
+            palette,
+        }
+    }
+
+    /// Presents the framebuffer. The texture is only uploaded again if the
+    /// display changed.
+    pub fn draw(&mut self, canvas: &mut WindowCanvas, framebuffer: &Framebuffer) {
+        if framebuffer.is_dirty() {
+            let (cols, rows) = framebuffer.resolution();
+            let query = self.texture.query();
+            if (query.width, query.height) != (cols as u32, rows as u32) {
+                self.texture = Self::create_texture(self.texture_creator, cols, rows);
+            }
+            self.texture
+                .update(None, &framebuffer.as_rgba(&self.palette.colors), cols * 4)
+                .unwrap();
+        }
+        canvas.copy(&self.texture, None, None).unwrap();
+        canvas.present();
+    }
+
+    fn create_texture(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        cols: usize,
+        rows: usize,
+    ) -> Texture<'a> {
+        texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, cols as u32, rows as u32)
+            .unwrap()
+    }
+}
@@ -0,0 +1,28 @@
+//! What the interpreter knows about a loaded ROM.
+
+use std::fmt;
+
+/// Returned by `Cpu::load_rom`. The SHA-1 identifies the ROM in the ROM
+/// database (see `romdb`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    /// Size in bytes
+    pub size: usize,
+    /// SHA-1 of the ROM file, in lowercase hex
+    pub sha1: String,
+}
+
+impl RomInfo {
+    pub fn new(rom: &[u8]) -> RomInfo {
+        RomInfo {
+            size: rom.len(),
+            sha1: sha1_smol::Sha1::from(rom).digest().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes, SHA-1 {}", self.size, self.sha1)
+    }
+}
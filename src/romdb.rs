@@ -0,0 +1,158 @@
+//! The ROM database: titles and settings of known ROMs, looked up by the
+//! SHA-1 that `Cpu::load_rom` returns.
+//!
+//! The format is the one of the community CHIP-8 database
+//! (<https://github.com/chip-8/chip-8-database>): `sha1-hashes.json` maps
+//! hashes to indexes into `programs.json`. The built-in database only covers
+//! the ROMs in `rom/`; `Database::load` reads the full community database
+//! from its `database` directory. Fields this interpreter does not use are
+//! ignored.
+//!
+//! ```no_run
+//! use r_chip_8::romdb::Database;
+//! use r_chip_8::Cpu;
+//!
+//! let mut cpu = Cpu::new();
+//! let info = cpu.load_rom("rom/c8games/BRIX").unwrap();
+//! if let Some(quirks) = Database::builtin().lookup(&info.sha1).and_then(|rom| rom.quirks) {
+//!     cpu.set_quirks(quirks);
+//! }
+//! ```
+
+use crate::palette::{parse_color, Palette};
+use crate::Quirks;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BUILTIN_HASHES: &str = include_str!("../data/romdb/sha1-hashes.json");
+const BUILTIN_PROGRAMS: &str = include_str!("../data/romdb/programs.json");
+
+#[derive(Debug, Clone, Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    tickrate: Option<u32>,
+    #[serde(default)]
+    start_address: Option<u16>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+    #[serde(default)]
+    colors: Option<Colors>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM, mapped to this interpreter's
+/// settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomMetadata {
+    pub title: String,
+    pub description: Option<String>,
+    /// The platform the ROM was written for, as named by the database
+    /// (`originalChip8`, `chip48`, `superchip`, `xochip`, ...)
+    pub platform: Option<String>,
+    /// The quirks profile of the first platform this interpreter has one for
+    pub quirks: Option<Quirks>,
+    /// Recommended instructions per frame
+    pub ipf: Option<u32>,
+    pub start_address: Option<u16>,
+    /// Game controls to CHIP-8 keys: `up`, `down`, `left`, `right`, `a`,
+    /// `b`, `player2Up`, ...
+    pub keys: BTreeMap<String, u8>,
+    pub palette: Option<Palette>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+}
+
+impl Database {
+    /// The database of the ROMs shipped in `rom/`
+    pub fn builtin() -> Database {
+        Database::from_json(BUILTIN_HASHES, BUILTIN_PROGRAMS)
+            .expect("invalid built-in ROM database")
+    }
+
+    /// Reads `sha1-hashes.json` and `programs.json` from `dir`
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Database> {
+        let hashes = fs::read_to_string(dir.as_ref().join("sha1-hashes.json"))?;
+        let programs = fs::read_to_string(dir.as_ref().join("programs.json"))?;
+        Database::from_json(&hashes, &programs)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn from_json(hashes: &str, programs: &str) -> serde_json::Result<Database> {
+        Ok(Database {
+            hashes: serde_json::from_str(hashes)?,
+            programs: serde_json::from_str(programs)?,
+        })
+    }
+
+    /// Number of programs in the database
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Looks up a ROM by its SHA-1, in hex
+    pub fn lookup(&self, sha1: &str) -> Option<RomMetadata> {
+        let sha1 = sha1.to_ascii_lowercase();
+        let program = self.programs.get(*self.hashes.get(&sha1)?)?;
+        let rom = program.roms.get(&sha1)?;
+        Some(RomMetadata {
+            title: program.title.clone(),
+            description: program.description.clone(),
+            platform: rom.platforms.first().cloned(),
+            quirks: rom.platforms.iter().find_map(|p| platform_quirks(p)),
+            ipf: rom.tickrate,
+            start_address: rom.start_address,
+            keys: rom.keys.clone(),
+            palette: rom.colors.as_ref().and_then(palette),
+        })
+    }
+}
+
+/// The quirks profile closest to a platform of the database
+fn platform_quirks(platform: &str) -> Option<Quirks> {
+    match platform {
+        "originalChip8" | "hybridVIP" | "chip8x" => Some(Quirks::VIP),
+        "chip48" | "superchip1" | "superchip" => Some(Quirks::SCHIP),
+        "modernChip8" | "xochip" => Some(Quirks::MODERN),
+        _ => None,
+    }
+}
+
+/// The pixel colours, over the default palette when there are fewer than 4
+fn palette(colors: &Colors) -> Option<Palette> {
+    if colors.pixels.is_empty() {
+        return None;
+    }
+    let mut palette = Palette::default();
+    for (slot, color) in palette.colors.iter_mut().zip(&colors.pixels) {
+        *slot = parse_color(color)?;
+    }
+    Some(palette)
+}
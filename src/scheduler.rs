@@ -0,0 +1,61 @@
+//! Frame pacing for the main loop.
+//!
+//! The interpreter is driven in 60 Hz frames: every frame runs a fixed number
+//! of instructions, ticks the timers and is rendered once. The scheduler keeps
+//! track of the next frame deadline so that small sleep inaccuracies do not
+//! accumulate into drift.
+//!
+//! Times are durations since an arbitrary fixed point, as returned by
+//! [`Host::now`](crate::host::Host::now), so the scheduler works with real and
+//! virtual clocks alike.
+
+use std::time::Duration;
+
+/// Frames per second of the original hardware (and of the timers).
+pub const FRAME_RATE: u32 = 60;
+
+/// How many frames the scheduler is allowed to catch up on at once before
+/// giving up and re-synchronising with the clock.
+const MAX_CATCH_UP: u32 = 5;
+
+#[derive(Debug)]
+pub struct FrameScheduler {
+    frame: Duration,
+    next: Duration,
+}
+
+impl FrameScheduler {
+    /// Creates a scheduler whose first frame is due one period after `now`.
+    pub fn new(now: Duration) -> FrameScheduler {
+        let frame = Duration::from_secs(1) / FRAME_RATE;
+        FrameScheduler {
+            frame,
+            next: now + frame,
+        }
+    }
+
+    /// When the next frame is due
+    pub fn deadline(&self) -> Duration {
+        self.next
+    }
+
+    /// Returns how many frames are due at `now` and advances the deadline
+    /// past them.
+    ///
+    /// The deadline advances by exactly one frame period each time, so the
+    /// error of a late wake-up is absorbed by the following frame instead of
+    /// accumulating. If the loop falls more than `MAX_CATCH_UP` frames behind
+    /// (e.g. the window was being dragged), the backlog is dropped.
+    pub fn frames_due(&mut self, now: Duration) -> u32 {
+        let mut due = 0;
+        while self.next <= now && due < MAX_CATCH_UP {
+            self.next += self.frame;
+            due += 1;
+        }
+        if self.next <= now {
+            self.next = now + self.frame;
+        }
+
+        due
+    }
+}
@@ -0,0 +1,228 @@
+//! `Host` implementation on top of SDL.
+
+use crate::debug_views::{DebugEvent, ViewKind};
+use crate::recording::Capture;
+use crate::renderer::Renderer;
+use r_chip_8::host::spin_sleep;
+use r_chip_8::{Framebuffer, Host, KeyState};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::render::WindowCanvas;
+use sdl2::EventPump;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How the main loop is synchronised with real time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sync {
+    /// `present` blocks on vsync; the host never sleeps.
+    Vsync,
+    /// The host sleeps until the next frame deadline.
+    Timer,
+}
+
+/// Why the host stopped running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    /// The main window was closed
+    Quit,
+    /// Escape: back to the launcher, if there is one
+    Back,
+    /// A ROM file was dropped onto the window
+    Load(String),
+}
+
+pub struct SdlHost<'a> {
+    canvas: WindowCanvas,
+    event_pump: EventPump,
+    renderer: Renderer<'a>,
+    sync: Sync,
+    start: Instant,
+    keys: KeyState,
+    /// Keys mapped to the game's controls by the ROM database
+    game_keys: Vec<(Keycode, u8)>,
+    exit: Option<Exit>,
+    capture: Capture,
+    /// Events for the debug views since the last `take_debug_events`
+    debug_events: Vec<DebugEvent>,
+}
+
+impl<'a> SdlHost<'a> {
+    pub fn new(
+        canvas: WindowCanvas,
+        event_pump: EventPump,
+        renderer: Renderer<'a>,
+        sync: Sync,
+        capture: Capture,
+    ) -> SdlHost<'a> {
+        SdlHost {
+            canvas,
+            event_pump,
+            renderer,
+            sync,
+            start: Instant::now(),
+            keys: KeyState::default(),
+            game_keys: Vec::new(),
+            exit: None,
+            capture,
+            debug_events: Vec::new(),
+        }
+    }
+
+    /// Maps the arrow keys, Space and Return to the controls `up`, `down`,
+    /// `left`, `right`, `a` and `b` of a ROM database entry
+    pub fn set_game_keys(&mut self, keys: &BTreeMap<String, u8>) {
+        self.game_keys = [
+            ("up", Keycode::Up),
+            ("down", Keycode::Down),
+            ("left", Keycode::Left),
+            ("right", Keycode::Right),
+            ("a", Keycode::Space),
+            ("b", Keycode::Return),
+        ]
+        .iter()
+        .filter_map(|(control, keycode)| Some((*keycode, *keys.get(*control)? & 0xF)))
+        .collect();
+    }
+
+    pub fn take_debug_events(&mut self) -> Vec<DebugEvent> {
+        std::mem::take(&mut self.debug_events)
+    }
+
+    /// Gives the window back for the next ROM or the launcher
+    pub fn into_parts(self) -> (WindowCanvas, EventPump, Capture, Exit) {
+        (
+            self.canvas,
+            self.event_pump,
+            self.capture,
+            self.exit.unwrap_or(Exit::Quit),
+        )
+    }
+}
+
+impl Host for SdlHost<'_> {
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        self.capture.frame(framebuffer, self.now());
+        self.renderer.draw(&mut self.canvas, framebuffer);
+    }
+
+    fn beep(&mut self, on: bool) {
+        if on {
+            println!("BEEP BOP");
+        }
+    }
+
+    fn audio_frame(&mut self, buzzer: bool) {
+        self.capture.audio_frame(buzzer);
+    }
+
+    fn poll_input(&mut self) -> KeyState {
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => self.exit = Some(Exit::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => self.exit = Some(Exit::Back),
+                Event::DropFile { filename, .. } => self.exit = Some(Exit::Load(filename)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => self.capture.request_screenshot(),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if window_id == self.canvas.window().id() {
+                        self.exit = Some(Exit::Quit);
+                    } else {
+                        self.debug_events.push(DebugEvent::Close(window_id));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = keypad(keycode).or(game_key(&self.game_keys, keycode)) {
+                        self.keys.press(key);
+                    } else if let Some(view) = ViewKind::from_keycode(keycode) {
+                        self.debug_events.push(DebugEvent::Toggle(view));
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = keypad(keycode).or(game_key(&self.game_keys, keycode)) {
+                        self.keys.release(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.keys
+    }
+
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn wait_until(&mut self, deadline: Duration) {
+        match self.sync {
+            // With vsync on a display faster than 60 Hz some refreshes have
+            // no frame due; the last frame is simply presented again.
+            Sync::Vsync => {}
+            Sync::Timer => {
+                let now = self.now();
+                if deadline > now {
+                    spin_sleep(deadline - now);
+                }
+            }
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.exit.is_none()
+    }
+}
+
+/// Maps the left side of a QWERTY keyboard onto the hex keypad:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+fn keypad(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num1 => Some(0x1),
+        Keycode::Num2 => Some(0x2),
+        Keycode::Num3 => Some(0x3),
+        Keycode::Num4 => Some(0xC),
+
+        Keycode::Q => Some(0x4),
+        Keycode::W => Some(0x5),
+        Keycode::E => Some(0x6),
+        Keycode::R => Some(0xD),
+
+        Keycode::A => Some(0x7),
+        Keycode::S => Some(0x8),
+        Keycode::D => Some(0x9),
+        Keycode::F => Some(0xE),
+
+        Keycode::Z => Some(0xA),
+        Keycode::X => Some(0x0),
+        Keycode::C => Some(0xB),
+        Keycode::V => Some(0xF),
+        _ => None,
+    }
+}
+
+fn game_key(game_keys: &[(Keycode, u8)], keycode: Keycode) -> Option<u8> {
+    game_keys
+        .iter()
+        .find(|(k, _)| *k == keycode)
+        .map(|(_, key)| *key)
+}
@@ -0,0 +1,147 @@
+//! Ready-made memory hooks: watchpoints for debuggers and access heatmaps
+//! for tooling.
+//!
+//! ```no_run
+//! use r_chip_8::memory::Access;
+//! use r_chip_8::watch::{Watchpoint, Watchpoints};
+//! use r_chip_8::Cpu;
+//!
+//! let mut cpu = Cpu::new();
+//! let mut watchpoints = Watchpoints::new();
+//! watchpoints.add(Watchpoint::new(0x300..0x310, &[Access::Write]));
+//! cpu.memory_mut().set_hook(Box::new(watchpoints));
+//!
+//! cpu.step();
+//! let hits = cpu.hook_mut::<Watchpoints>().unwrap().take_hits();
+//! ```
+
+use crate::memory::{Access, MemoryHook, SIZE};
+use std::ops::Range;
+
+/// Watches a range of addresses for some kinds of access
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<u16>,
+    read: bool,
+    write: bool,
+    execute: bool,
+}
+
+impl Watchpoint {
+    pub fn new(range: Range<u16>, accesses: &[Access]) -> Watchpoint {
+        Watchpoint {
+            range,
+            read: accesses.contains(&Access::Read),
+            write: accesses.contains(&Access::Write),
+            execute: accesses.contains(&Access::Execute),
+        }
+    }
+
+    pub fn matches(&self, access: Access, address: u16) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        kind && self.range.contains(&address)
+    }
+}
+
+/// An access that triggered a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    pub access: Access,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Collects the accesses that match any of its watchpoints. A debugger
+/// checks `take_hits` after every `Cpu::step` and stops if there are any.
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    hits: Vec<Hit>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes the watchpoints equal to `watchpoint`, returning whether there
+    /// were any.
+    pub fn remove(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns and forgets the hits since the last call
+    pub fn take_hits(&mut self) -> Vec<Hit> {
+        std::mem::take(&mut self.hits)
+    }
+}
+
+impl MemoryHook for Watchpoints {
+    fn access(&mut self, access: Access, address: u16, value: u8) {
+        if self.watchpoints.iter().any(|w| w.matches(access, address)) {
+            self.hits.push(Hit {
+                access,
+                address,
+                value,
+            });
+        }
+    }
+}
+
+/// Counts every access to every byte, by kind: which bytes a ROM reads (as
+/// sprites or data), writes and executes.
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    /// Indexed by `Access as usize`, then by address
+    counts: [Vec<u32>; 3],
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap {
+            counts: [vec![0; SIZE], vec![0; SIZE], vec![0; SIZE]],
+        }
+    }
+
+    pub fn count(&self, access: Access, address: u16) -> u32 {
+        self.counts[access as usize][address as usize]
+    }
+
+    /// Counts for every address, for one kind of access
+    pub fn counts(&self, access: Access) -> &[u32] {
+        &self.counts[access as usize]
+    }
+
+    pub fn clear(&mut self) {
+        for counts in &mut self.counts {
+            counts.fill(0);
+        }
+    }
+}
+
+impl Default for Heatmap {
+    fn default() -> Heatmap {
+        Heatmap::new()
+    }
+}
+
+impl MemoryHook for Heatmap {
+    fn access(&mut self, access: Access, address: u16, _value: u8) {
+        let count = &mut self.counts[access as usize][address as usize];
+        *count = count.saturating_add(1);
+    }
+}
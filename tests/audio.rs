@@ -0,0 +1,64 @@
+//! Renders the buzzer headlessly and compares the WAV output with golden
+//! files in `tests/golden`. Run with `UPDATE_GOLDEN=1` to regenerate them
+//! after an intentional change to the audio engine.
+
+use r_chip_8::audio::{AudioRecorder, Waveform, SAMPLES_PER_FRAME};
+use r_chip_8::Cpu;
+use std::fs;
+use std::path::Path;
+
+fn check_golden(name: &str, audio: &AudioRecorder) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    let mut wav = Vec::new();
+    audio.write_wav(&mut wav).unwrap();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &wav).unwrap();
+    }
+    let golden = fs::read(&path).unwrap();
+    assert!(wav == golden, "{} differs from the golden file", name);
+}
+
+/// Runs `rom` for `frames` frames, recording the buzzer.
+fn record(rom: &[u8], frames: usize, waveform: Waveform) -> AudioRecorder {
+    let mut cpu = Cpu::new();
+    cpu.load_rom_bytes(rom).unwrap();
+    let mut audio = AudioRecorder::new(waveform);
+    for _ in 0..frames {
+        cpu.run_frame(10);
+        audio.frame(cpu.buzzer());
+    }
+    audio
+}
+
+// LD V0, 05; LD ST, V0; JP 204
+const BEEP_5_FRAMES: [u8; 6] = [0x60, 0x05, 0xF0, 0x18, 0x12, 0x04];
+
+#[test]
+fn sound_timer_is_frame_accurate() {
+    let audio = record(&BEEP_5_FRAMES, 8, Waveform::default());
+    let samples = audio.samples();
+    assert_eq!(samples.len(), 8 * SAMPLES_PER_FRAME);
+
+    let (beep, silence) = samples.split_at(5 * SAMPLES_PER_FRAME);
+    assert!(beep.iter().all(|&sample| sample != 0));
+    assert!(silence.iter().all(|&sample| sample == 0));
+}
+
+#[test]
+fn square_wave_matches_golden() {
+    let audio = record(&BEEP_5_FRAMES, 8, Waveform::default());
+    check_golden("beep_square.wav", &audio);
+}
+
+#[test]
+fn xo_chip_pattern_matches_golden() {
+    let pattern = [
+        0x00, 0xFF, 0x00, 0xFF, 0x0F, 0x0F, 0x0F, 0x0F, 0x33, 0x33, 0x33, 0x33, 0x55, 0x55, 0x55,
+        0x55,
+    ];
+    let audio = record(&BEEP_5_FRAMES, 8, Waveform::Pattern { pattern, pitch: 64 });
+    check_golden("beep_pattern.wav", &audio);
+}
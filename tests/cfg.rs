@@ -0,0 +1,80 @@
+//! Control-flow graphs of small hand-assembled ROMs and of a real game.
+
+use r_chip_8::cfg::{Cfg, Edge, EdgeKind, Warning};
+use std::fs;
+
+fn edge(kind: EdgeKind, target: u16) -> Edge {
+    Edge { kind, target }
+}
+
+#[test]
+fn splits_blocks_at_skips_calls_and_returns() {
+    // 200: LD V0, 01
+    // 202: SE V0, 01
+    // 204: CALL 20A
+    // 206: JP 206
+    // 208: DW FFFF    (data, never reached)
+    // 20A: ADD V0, 01
+    // 20C: RET
+    let rom = [
+        0x60, 0x01, 0x30, 0x01, 0x22, 0x0A, 0x12, 0x06, 0xFF, 0xFF, 0x70, 0x01, 0x00, 0xEE,
+    ];
+    let cfg = Cfg::build(&rom, 0x200);
+
+    let starts: Vec<u16> = cfg.blocks.keys().copied().collect();
+    assert_eq!(starts, [0x200, 0x204, 0x206, 0x20A]);
+    assert_eq!(
+        cfg.blocks[&0x200].successors,
+        [
+            edge(EdgeKind::Fallthrough, 0x204),
+            edge(EdgeKind::Skip, 0x206)
+        ]
+    );
+    assert_eq!(
+        cfg.blocks[&0x204].successors,
+        [
+            edge(EdgeKind::Call, 0x20A),
+            edge(EdgeKind::Fallthrough, 0x206)
+        ]
+    );
+    assert_eq!(cfg.blocks[&0x206].successors, [edge(EdgeKind::Jump, 0x206)]);
+    assert!(cfg.blocks[&0x20A].is_exit());
+    assert_eq!(cfg.blocks[&0x20A].instructions.len(), 2);
+    assert!(cfg.subroutines.contains(&0x20A));
+    assert!(cfg.warnings.is_empty());
+    assert!(cfg.block_at(0x208).is_none());
+    assert_eq!(cfg.block_at(0x20C).unwrap().start, 0x20A);
+}
+
+#[test]
+fn warns_about_indirect_jumps_and_leaving_the_rom() {
+    // 200: SE V0, 00
+    // 202: JP V0, 300
+    // 204: JP 400
+    let rom = [0x30, 0x00, 0xB3, 0x00, 0x14, 0x00];
+    let cfg = Cfg::build(&rom, 0x200);
+
+    assert_eq!(
+        cfg.warnings,
+        [
+            Warning::IndirectJump { pc: 0x202 },
+            Warning::OutsideRom {
+                pc: 0x204,
+                target: 0x400
+            },
+        ]
+    );
+    assert!(cfg.to_dot().contains("b204 -> b400;"));
+}
+
+#[test]
+fn finds_self_modifying_code_in_15puzzle() {
+    let rom = fs::read("rom/c8games/15PUZZLE").unwrap();
+    let cfg = Cfg::build(&rom, 0x200);
+
+    // 208: LD I, 203 and 20C: LD [I], V0 patch the operand of 202: LD VC, 00
+    assert!(cfg.warnings.contains(&Warning::SelfModifying {
+        pc: 0x20C,
+        address: 0x203
+    }));
+}
@@ -0,0 +1,102 @@
+//! Test support shared by the integration tests.
+
+// Each test crate uses a different part of this module
+#![allow(dead_code)]
+
+use r_chip_8::{Cpu, Quirks};
+
+/// Builds a `Cpu` with its registers, I and a program set up, so that tests
+/// state only the part of the machine they depend on:
+///
+/// ```ignore
+/// let cpu = CpuBuilder::new().register(1, 0x20).program(&[0x8014]).run();
+/// ```
+pub struct CpuBuilder {
+    quirks: Quirks,
+    registers: [u8; 16],
+    i: u16,
+    pc: u16,
+    program: Vec<u16>,
+    data: Vec<(u16, Vec<u8>)>,
+}
+
+impl CpuBuilder {
+    /// Modern quirks, every register 0 and no program at 0x200
+    pub fn new() -> CpuBuilder {
+        CpuBuilder {
+            quirks: Quirks::MODERN,
+            registers: [0; 16],
+            i: 0,
+            pc: 0x200,
+            program: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> CpuBuilder {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn register(mut self, x: usize, value: u8) -> CpuBuilder {
+        self.registers[x] = value;
+        self
+    }
+
+    pub fn registers(mut self, registers: [u8; 16]) -> CpuBuilder {
+        self.registers = registers;
+        self
+    }
+
+    pub fn i(mut self, i: u16) -> CpuBuilder {
+        self.i = i;
+        self
+    }
+
+    /// Where the program is loaded and starts
+    pub fn pc(mut self, pc: u16) -> CpuBuilder {
+        self.pc = pc;
+        self
+    }
+
+    pub fn program(mut self, opcodes: &[u16]) -> CpuBuilder {
+        self.program = opcodes.to_vec();
+        self
+    }
+
+    /// Loads `bytes` at `address`, after the program
+    pub fn data(mut self, address: u16, bytes: &[u8]) -> CpuBuilder {
+        self.data.push((address, bytes.to_vec()));
+        self
+    }
+
+    pub fn build(self) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(self.quirks);
+        let bytes: Vec<u8> = self
+            .program
+            .iter()
+            .flat_map(|op| op.to_be_bytes())
+            .collect();
+        cpu.memory_mut().load(self.pc as usize, &bytes);
+        for (address, bytes) in &self.data {
+            cpu.memory_mut().load(*address as usize, bytes);
+        }
+        cpu.set_pc(self.pc);
+        cpu.set_i(self.i);
+        for (x, value) in self.registers.into_iter().enumerate() {
+            cpu.set_register(x, value);
+        }
+        cpu
+    }
+
+    /// Builds the `Cpu` and steps once per instruction of the program
+    pub fn run(self) -> Cpu {
+        let steps = self.program.len();
+        let mut cpu = self.build();
+        for _ in 0..steps {
+            cpu.step();
+        }
+        cpu
+    }
+}
@@ -0,0 +1,54 @@
+//! Coverage counts of a small hand-assembled ROM.
+
+use r_chip_8::coverage::Coverage;
+use r_chip_8::Cpu;
+
+// 200: LD V0, 00
+// 202: ADD V0, 01
+// 204: SE V0, 03
+// 206: JP 202
+// 208: JP 208
+// 20A: CLS        (never reached)
+const COUNT_TO_3: [u8; 12] = [
+    0x60, 0x00, 0x70, 0x01, 0x30, 0x03, 0x12, 0x02, 0x12, 0x08, 0x00, 0xE0,
+];
+
+#[test]
+fn counts_instructions_and_skips() {
+    let mut cpu = Cpu::new();
+    cpu.load_rom_bytes(&COUNT_TO_3).unwrap();
+    let mut coverage = Coverage::new();
+    for _ in 0..12 {
+        coverage.step(&mut cpu);
+    }
+
+    assert_eq!(coverage.hits(0x200), 1);
+    assert_eq!(coverage.hits(0x202), 3);
+    assert_eq!(coverage.skips(0x204), (1, 2));
+    assert_eq!(coverage.hits(0x206), 2);
+    // 1 + 3 * 2 + 2, leaving 3 steps on the last JP
+    assert_eq!(coverage.hits(0x208), 3);
+
+    let listing = coverage.listing(&COUNT_TO_3, 0x200);
+    assert!(listing
+        .text
+        .starts_with("; 5 of 5 instructions executed (100.0%)\n; 2 of 2 skip branches"));
+    let lcov = coverage.lcov("count.lst", &listing.lines, &COUNT_TO_3, 0x200);
+    let line = listing.lines[&0x204];
+    assert!(lcov.contains(&format!("BRDA:{},0,0,1\nBRDA:{},0,1,2\n", line, line)));
+    assert!(lcov.contains(&format!("DA:{},3\n", listing.lines[&0x202])));
+    assert!(lcov.ends_with("LF:5\nLH:5\nend_of_record\n"));
+}
+
+#[test]
+fn counts_skips_that_wrap_around_memory() {
+    let mut cpu = Cpu::new();
+    cpu.set_start(0xFFE).unwrap();
+    // FFE: SE V0, 00
+    cpu.load_rom_bytes(&[0x30, 0x00]).unwrap();
+    let mut coverage = Coverage::new();
+    coverage.step(&mut cpu);
+
+    assert_eq!(cpu.pc(), 0x002);
+    assert_eq!(coverage.skips(0xFFE), (1, 0));
+}
@@ -0,0 +1,292 @@
+//! `Cpu` against the reference interpreter in `reference/`: both run the same
+//! ROM, with the same quirks, seed and key presses, one instruction at a time,
+//! and must be in the same state after every instruction.
+
+mod reference;
+
+use r_chip_8::memory::SIZE;
+use r_chip_8::quirks::StackPolicy;
+use r_chip_8::{Cpu, Instruction, Quirks};
+use reference::Reference;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+const SEED: u64 = 0xC8;
+const FRAMES: u32 = 300;
+const IPF: u32 = 15;
+
+/// The first instruction after which `Cpu` and the reference disagree
+struct Divergence {
+    frame: u32,
+    step: u32,
+    pc: u16,
+    opcode: u16,
+    /// One line per differing part of the state
+    differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "frame {} step {}: {:03X}: {:04X}  {}",
+            self.frame,
+            self.step,
+            self.pc,
+            self.opcode,
+            Instruction::decode(self.opcode)
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "  {}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes every difference between the states of `cpu` and `reference`.
+/// The display is only compared when `display` is set, as it is slow.
+fn compare(cpu: &Cpu, reference: &Reference, display: bool) -> Vec<String> {
+    let mut differences = Vec::new();
+    // Formats only what differs, as this runs after every instruction
+    macro_rules! check {
+        ($name:expr, $cpu:expr, $reference:expr, $format:literal) => {
+            if $cpu != $reference {
+                differences.push(format!(
+                    concat!("{}: cpu ", $format, ", reference ", $format),
+                    $name, $cpu, $reference
+                ));
+            }
+        };
+    }
+    for (x, (&a, &b)) in cpu.registers().iter().zip(&reference.v).enumerate() {
+        check!(format!("V{:X}", x), a, b, "{:02X}");
+    }
+    check!("I", cpu.i(), reference.i, "{:03X}");
+    check!("PC", cpu.pc(), reference.pc, "{:03X}");
+    check!(
+        "stack",
+        cpu.stack(),
+        &reference.stack[..reference.sp],
+        "{:03X?}"
+    );
+    check!("DT", cpu.dt(), reference.dt, "{}");
+    check!("ST", cpu.st(), reference.st, "{}");
+    check!(
+        "vblank wait",
+        cpu.in_vblank_wait(),
+        reference.vblank_wait,
+        "{}"
+    );
+    check!("fault", cpu.fault(), reference.fault, "{:?}");
+
+    let memory = cpu.memory().read_chunk(0, SIZE);
+    if memory != reference.memory {
+        let address = (0..SIZE)
+            .find(|&a| memory[a] != reference.memory[a])
+            .unwrap();
+        check!(
+            format!("memory at {:03X}", address),
+            memory[address],
+            reference.memory[address],
+            "{:02X}"
+        );
+    }
+
+    if display {
+        let framebuffer = cpu.framebuffer();
+        let resolution = (reference.pixels[0].len(), reference.pixels.len());
+        check!("resolution", framebuffer.resolution(), resolution, "{:?}");
+        if framebuffer.resolution() == resolution {
+            let (width, height) = resolution;
+            let pixel = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .find(|&(x, y)| (framebuffer.pixel(x, y) != 0) != reference.pixels[y][x]);
+            if let Some((x, y)) = pixel {
+                check!(
+                    format!("pixel ({}, {})", x, y),
+                    framebuffer.pixel(x, y),
+                    reference.pixels[y][x] as u8,
+                    "{}"
+                );
+            }
+        }
+    }
+    differences
+}
+
+/// Runs `rom` in `Cpu` and in the reference for `frames` frames of up to `ipf`
+/// instructions, holding a different key every 20 frames.
+fn run_lockstep(rom: &[u8], quirks: Quirks, frames: u32, ipf: u32) -> Result<(), Divergence> {
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(quirks);
+    cpu.set_seed(SEED);
+    cpu.load_rom_bytes(rom).unwrap();
+    let mut reference = Reference::new(rom, quirks, SEED);
+
+    for frame in 0..frames {
+        let key = (frame / 20 % 16) as u8;
+        match frame % 20 {
+            5 => {
+                cpu.key_press(key);
+                reference.key_press(key);
+            }
+            10 => {
+                cpu.key_release(key);
+                reference.key_release(key);
+            }
+            _ => {}
+        }
+        for step in 0..ipf {
+            let (pc, opcode) = (reference.pc, reference.opcode());
+            cpu.step();
+            reference.step();
+            // Only instructions that draw, and broken ones, touch the display
+            let display = cpu.take_dirty() || matches!(opcode >> 12, 0x0 | 0xD);
+            let differences = compare(&cpu, &reference, display);
+            if !differences.is_empty() {
+                return Err(Divergence {
+                    frame,
+                    step,
+                    pc,
+                    opcode,
+                    differences,
+                });
+            }
+            if reference.vblank_wait {
+                break;
+            }
+        }
+        cpu.end_frame();
+        reference.end_frame();
+    }
+    Ok(())
+}
+
+fn roms() -> Vec<PathBuf> {
+    let mut roms: Vec<PathBuf> = ["rom", "rom/c8games"]
+        .iter()
+        .flat_map(|dir| fs::read_dir(dir).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    roms.sort();
+    roms
+}
+
+#[test]
+fn bundled_roms_match_the_reference() {
+    let profiles = [
+        ("VIP", Quirks::VIP),
+        ("SCHIP", Quirks::SCHIP),
+        ("modern", Quirks::MODERN),
+    ];
+    for path in roms() {
+        let rom = fs::read(&path).unwrap();
+        for (name, quirks) in profiles {
+            if let Err(divergence) = run_lockstep(&rom, quirks, FRAMES, IPF) {
+                panic!("{} ({}) diverged at {}", path.display(), name, divergence);
+            }
+        }
+    }
+}
+
+// Every flag-setting ALU instruction, with VF as the destination and as the
+// source, on operands that do and do not carry or borrow, and equal ones.
+const ALU: [u8; 50] = [
+    0x6F, 0x05, // LD VF, 05
+    0x61, 0x03, // LD V1, 03
+    0x8F, 0x14, // ADD VF, V1   (no carry)
+    0x6F, 0xFF, // LD VF, FF
+    0x8F, 0x14, // ADD VF, V1   (carry)
+    0x81, 0xF4, // ADD V1, VF
+    0x6F, 0x03, // LD VF, 03
+    0x8F, 0x15, // SUB VF, V1   (equal)
+    0x81, 0xF5, // SUB V1, VF
+    0x6F, 0x02, // LD VF, 02
+    0x8F, 0x17, // SUBN VF, V1
+    0x81, 0xF7, // SUBN V1, VF
+    0x6F, 0x81, // LD VF, 81
+    0x8F, 0x06, // SHR VF
+    0x6F, 0x81, // LD VF, 81
+    0x8F, 0x0E, // SHL VF
+    0x60, 0x05, // LD V0, 05
+    0x62, 0x05, // LD V2, 05
+    0x80, 0x25, // SUB V0, V2   (equal: no borrow)
+    0x80, 0x24, // ADD V0, V2   (no carry clears VF)
+    0x80, 0x27, // SUBN V0, V2
+    0x80, 0x06, // SHR V0
+    0x80, 0x0E, // SHL V0
+    0x83, 0x25, // SUB V3, V2   (borrow)
+    0x12, 0x30, // JP 230
+];
+
+#[test]
+fn alu_flags_match_the_reference() {
+    if let Err(divergence) = run_lockstep(&ALU, Quirks::MODERN, 2, 30) {
+        panic!("diverged at {}", divergence);
+    }
+}
+
+// Recurses until V0 is 5, then returns once too often, and starts over:
+// 200: CALL 206
+// 202: RET
+// 204: JP 200
+// 206: ADD V0, 01
+// 208: SE V0, 05
+// 20A: CALL 206
+// 20C: RET
+const RECURSION: [u8; 14] = [
+    0x22, 0x06, 0x00, 0xEE, 0x12, 0x00, 0x70, 0x01, 0x30, 0x05, 0x22, 0x06, 0x00, 0xEE,
+];
+
+#[test]
+fn stack_policies_match_the_reference() {
+    for stack_policy in [StackPolicy::Error, StackPolicy::Wrap, StackPolicy::Ignore] {
+        for stack_depth in [0, 2, 16] {
+            let quirks = Quirks {
+                stack_depth,
+                stack_policy,
+                ..Quirks::MODERN
+            };
+            if let Err(divergence) = run_lockstep(&RECURSION, quirks, 10, 100) {
+                panic!(
+                    "{:?} with depth {} diverged at {}",
+                    stack_policy, stack_depth, divergence
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn divergences_name_the_instruction_and_registers() {
+    // Under VIP quirks Cpu waits for the vertical blank and clips the sprite,
+    // the reference under modern quirks wraps it
+    // 200: LD V0, 3C
+    // 202: LD I, 050
+    // 204: DRW V0, V0, 5
+    let rom = [0x60, 0x3C, 0xA0, 0x50, 0xD0, 0x05];
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(Quirks::VIP);
+    cpu.load_rom_bytes(&rom).unwrap();
+    let mut reference = Reference::new(&rom, Quirks::MODERN, SEED);
+    for _ in 0..3 {
+        cpu.step();
+        reference.step();
+    }
+    let differences = compare(&cpu, &reference, true);
+    assert!(differences.contains(&"vblank wait: cpu true, reference false".to_string()));
+    assert!(differences.iter().any(|d| d.starts_with("pixel")));
+
+    let divergence = Divergence {
+        frame: 0,
+        step: 2,
+        pc: 0x204,
+        opcode: 0xD005,
+        differences,
+    };
+    assert!(divergence
+        .to_string()
+        .starts_with("frame 0 step 2: 204: D005  DRW V0, V0, 5\n  vblank wait:"));
+}
@@ -0,0 +1,107 @@
+//! The framebuffer: packed rows, planes, dirty tracking and RGBA export.
+
+mod common;
+
+use common::CpuBuilder;
+use r_chip_8::display::COLORS;
+use r_chip_8::{Framebuffer, Rgba};
+
+const PALETTE: [Rgba; COLORS] = [
+    [0, 0, 0, 255],
+    [1, 1, 1, 255],
+    [2, 2, 2, 255],
+    [3, 3, 3, 255],
+];
+
+#[test]
+fn rows_are_packed_from_the_most_significant_bit() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.xor_row(0, 3, 1 << 127 | 1 << 64);
+    assert_eq!(framebuffer.pixel(0, 3), 1);
+    assert_eq!(framebuffer.pixel(63, 3), 1);
+    assert_eq!(framebuffer.pixel(1, 3), 0);
+    assert_eq!(framebuffer.row(0, 3), 1 << 127 | 1 << 64);
+    assert_eq!(framebuffer.row(0, 2), 0);
+}
+
+#[test]
+fn planes_are_bits_of_the_colour_index() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    framebuffer.xor_row(0, 0, 0b11 << 126);
+    framebuffer.xor_row(1, 0, 0b101 << 125);
+    // Pixel 0 is in both planes, pixel 1 in plane 0 and pixel 2 in plane 1
+    assert_eq!([0, 1, 2, 3].map(|x| framebuffer.pixel(x, 0)), [3, 1, 2, 0]);
+    assert_eq!(framebuffer.row(0, 0), 0b11 << 126);
+    assert_eq!(framebuffer.row(1, 0), 0b101 << 125);
+
+    // Collisions are per plane
+    assert!(!framebuffer.xor_row(1, 0, 1 << 126));
+    assert!(framebuffer.xor_row(0, 0, 1 << 126));
+    assert_eq!(framebuffer.pixel(1, 0), 2);
+
+    framebuffer.clear();
+    assert_eq!(framebuffer.row(0, 0) | framebuffer.row(1, 0), 0);
+}
+
+#[test]
+fn as_rgba_looks_up_every_colour_index() {
+    let mut framebuffer = Framebuffer::new(4, 2);
+    framebuffer.xor_row(0, 0, 0b0101 << 124);
+    framebuffer.xor_row(1, 0, 0b0011 << 124);
+    framebuffer.xor_row(0, 1, 1 << 127);
+    let rgba = framebuffer.as_rgba(&PALETTE);
+    assert_eq!(rgba.len(), 4 * 2 * 4);
+    let indexes: Vec<u8> = rgba.chunks(4).map(|pixel| pixel[0]).collect();
+    assert_eq!(indexes, [0, 1, 2, 3, 1, 0, 0, 0]);
+}
+
+#[test]
+fn take_dirty_reports_changes_once() {
+    let mut framebuffer = Framebuffer::new(64, 32);
+    assert!(framebuffer.take_dirty(), "a new framebuffer is dirty");
+    assert!(!framebuffer.take_dirty());
+
+    framebuffer.xor_row(0, 0, 0);
+    assert!(!framebuffer.is_dirty(), "nothing was drawn");
+    framebuffer.xor_row(1, 5, 1);
+    assert!(framebuffer.is_dirty());
+    assert!(framebuffer.take_dirty());
+    assert!(!framebuffer.is_dirty());
+
+    framebuffer.clear();
+    assert!(framebuffer.take_dirty());
+    framebuffer.resize(128, 64);
+    assert!(framebuffer.take_dirty());
+}
+
+#[test]
+fn equality_ignores_the_dirty_flag() {
+    let mut a = Framebuffer::new(64, 32);
+    let b = Framebuffer::new(64, 32);
+    a.take_dirty();
+    assert!(a == b);
+    a.xor_row(0, 0, 1 << 127);
+    assert!(a != b);
+}
+
+#[test]
+fn high_and_low_resize_and_clear_the_display() {
+    // DRW V0, V0, 5 (the digit 0 in the font); HIGH; DRW V0, V0, 5; LOW
+    let mut cpu = CpuBuilder::new()
+        .i(0x50)
+        .program(&[0xD005, 0x00FF, 0xD005, 0x00FE])
+        .build();
+    cpu.step();
+    assert_eq!(cpu.framebuffer().resolution(), (64, 32));
+    assert_ne!(cpu.framebuffer().row(0, 0), 0);
+
+    cpu.step();
+    assert_eq!(cpu.framebuffer().resolution(), (128, 64));
+    assert!((0..64).all(|y| cpu.framebuffer().row(0, y) == 0));
+
+    cpu.step();
+    assert_ne!(cpu.framebuffer().row(0, 0), 0);
+    cpu.step();
+    assert_eq!(cpu.framebuffer().resolution(), (64, 32));
+    assert!((0..32).all(|y| cpu.framebuffer().row(0, y) == 0));
+}
@@ -0,0 +1,152 @@
+//! The cached engine against the interpreter: both must end every
+//! instruction in the same state.
+
+use r_chip_8::memory::SIZE;
+use r_chip_8::{Cpu, Engine, Quirks};
+use std::fs;
+use std::path::PathBuf;
+
+const FRAMES: u32 = 300;
+const IPF: u32 = 15;
+
+fn roms() -> Vec<PathBuf> {
+    let mut roms: Vec<PathBuf> = ["rom", "rom/c8games"]
+        .iter()
+        .flat_map(|dir| fs::read_dir(dir).unwrap())
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    roms.sort();
+    roms
+}
+
+fn cpu(rom: &[u8], quirks: Quirks, engine: Engine) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(quirks);
+    cpu.set_seed(0xC8);
+    cpu.set_engine(engine);
+    cpu.load_rom_bytes(rom).unwrap();
+    cpu
+}
+
+fn assert_same_registers(reference: &Cpu, cached: &Cpu, context: &str) {
+    assert_eq!(reference.pc(), cached.pc(), "PC, {}", context);
+    assert_eq!(reference.i(), cached.i(), "I, {}", context);
+    assert_eq!(reference.registers(), cached.registers(), "V, {}", context);
+    assert_eq!(reference.stack(), cached.stack(), "stack, {}", context);
+    assert_eq!(reference.dt(), cached.dt(), "DT, {}", context);
+    assert_eq!(reference.st(), cached.st(), "ST, {}", context);
+    assert_eq!(reference.fault(), cached.fault(), "fault, {}", context);
+}
+
+#[test]
+fn bundled_roms_run_the_same() {
+    for path in roms() {
+        let rom = fs::read(&path).unwrap();
+        for (name, quirks) in [("modern", Quirks::MODERN), ("VIP", Quirks::VIP)] {
+            let mut reference = cpu(&rom, quirks, Engine::Interpreter);
+            let mut cached = cpu(&rom, quirks, Engine::Cached);
+            for frame in 0..FRAMES {
+                // Hold a different key every 20 frames, with a pause between
+                let key = (frame / 20 % 16) as u8;
+                for cpu in [&mut reference, &mut cached] {
+                    match frame % 20 {
+                        5 => cpu.key_press(key),
+                        10 => cpu.key_release(key),
+                        _ => {}
+                    }
+                }
+                for step in 0..IPF {
+                    reference.step();
+                    cached.step();
+                    let context = format!(
+                        "{} ({}), frame {} step {}",
+                        path.display(),
+                        name,
+                        frame,
+                        step
+                    );
+                    assert_same_registers(&reference, &cached, &context);
+                    if reference.in_vblank_wait() {
+                        break;
+                    }
+                }
+                reference.end_frame();
+                cached.end_frame();
+                let context = format!("{} ({}), frame {}", path.display(), name, frame);
+                assert!(
+                    reference.framebuffer() == cached.framebuffer(),
+                    "display, {}",
+                    context
+                );
+                assert_eq!(
+                    reference.memory().read_chunk(0, SIZE),
+                    cached.memory().read_chunk(0, SIZE),
+                    "memory, {}",
+                    context
+                );
+            }
+        }
+    }
+}
+
+// 200: CALL 210
+// 202: LD V0, 60
+// 204: LD V1, 07
+// 206: LD I, 210
+// 208: LD [I], V1  (patches 210 to LD V0, 07)
+// 20A: CALL 210
+// 20C: JP 20C
+// 210: LD V0, 01
+// 212: RET
+const SELF_MODIFYING: [u8; 20] = [
+    0x22, 0x10, 0x60, 0x60, 0x61, 0x07, 0xA2, 0x10, 0xF1, 0x55, 0x22, 0x10, 0x12, 0x0C, 0x00, 0x00,
+    0x60, 0x01, 0x00, 0xEE,
+];
+
+#[test]
+fn writes_invalidate_decoded_instructions() {
+    let mut reference = cpu(&SELF_MODIFYING, Quirks::MODERN, Engine::Interpreter);
+    let mut cached = cpu(&SELF_MODIFYING, Quirks::MODERN, Engine::Cached);
+    for step in 0..12 {
+        reference.step();
+        cached.step();
+        assert_same_registers(&reference, &cached, &format!("step {}", step));
+    }
+    assert_eq!(cached.registers()[0], 0x07);
+    assert_eq!(cached.pc(), 0x20C);
+}
+
+// 200: CALL 210
+// 202: LD V0, 07
+// 204: LD I, 211
+// 206: JP FFF
+// 210: LD V1, 01
+// 212: RET
+// FFF: LD [I], V0  (split across FFF and 000, patches 210 to LD V1, 07)
+// 001: CALL 210
+// 003: JP 003
+const WRAPPED_WRITE: [u8; 20] = [
+    0x22, 0x10, 0x60, 0x07, 0xA2, 0x11, 0x1F, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x61, 0x01, 0x00, 0xEE,
+];
+
+#[test]
+fn writes_from_the_end_of_memory_invalidate_decoded_instructions() {
+    let mut reference = cpu(&WRAPPED_WRITE, Quirks::MODERN, Engine::Interpreter);
+    let mut cached = cpu(&WRAPPED_WRITE, Quirks::MODERN, Engine::Cached);
+    for cpu in [&mut reference, &mut cached] {
+        let memory = cpu.memory_mut();
+        memory.write(0xFFF, 0xF0).unwrap();
+        memory
+            .write_vec(0x000, vec![0x55, 0x22, 0x10, 0x10, 0x03])
+            .unwrap();
+    }
+    for step in 0..12 {
+        reference.step();
+        cached.step();
+        assert_same_registers(&reference, &cached, &format!("step {}", step));
+    }
+    assert_eq!(cached.registers()[1], 0x07);
+    assert_eq!(cached.pc(), 0x003);
+}
@@ -0,0 +1,225 @@
+//! Drives the GDB stub over TCP with a minimal RSP client.
+
+use r_chip_8::gdb::GdbStub;
+use r_chip_8::memory::Access;
+use r_chip_8::watch::{Heatmap, Watchpoints};
+use r_chip_8::Cpu;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+// 200: LD V0, 01
+// 202: ADD V0, 01
+// 204: LD I, 300
+// 206: LD [I], V0
+// 208: JP 202
+const COUNTER: [u8; 10] = [0x60, 0x01, 0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.stream.write_all(packet.as_bytes()).unwrap();
+        assert_eq!(self.read_byte(), b'+', "packet {} not acknowledged", data);
+    }
+
+    fn receive(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                b => data.push(b),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// PC from the `g` packet: after V0-VF (16 bytes) and I (2 bytes)
+    fn pc(&mut self) -> u16 {
+        let registers = self.request("g");
+        u16::from_str_radix(&registers[36..40], 16).unwrap()
+    }
+}
+
+/// Starts a stub for `rom` on a free port. The thread ends once the client
+/// detached, returning the CPU.
+fn start(rom: &[u8]) -> (Client, JoinHandle<Cpu>) {
+    start_with(rom, |_| {})
+}
+
+/// Like `start`, calling `setup` on the CPU before the client connects
+fn start_with(rom: &[u8], setup: fn(&mut Cpu)) -> (Client, JoinHandle<Cpu>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let rom = rom.to_vec();
+    let server = thread::spawn(move || {
+        let mut cpu = Cpu::new();
+        cpu.load_rom_bytes(&rom).unwrap();
+        setup(&mut cpu);
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        while stub.is_connected() {
+            stub.run_frame(&mut cpu, 10);
+        }
+        cpu
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn reads_registers_and_memory() {
+    let (mut client, server) = start(&COUNTER);
+
+    assert_eq!(client.request("?"), "S05");
+    let registers = client.request("g");
+    // V0-VF, I, PC, SP, DT, ST
+    assert_eq!(registers.len(), (16 + 2 + 2 + 1 + 1 + 1) * 2);
+    assert_eq!(client.pc(), 0x200);
+    assert_eq!(client.request("p11"), "0200");
+    assert_eq!(client.request("m200,4"), "60017001");
+    // Past the end of memory
+    assert_eq!(client.request("m1000,1"), "E01");
+    assert!(client
+        .request("qSupported:multiprocess+")
+        .contains("qXfer:features:read+"));
+    assert!(client
+        .request("qXfer:features:read:target.xml:0,fff")
+        .starts_with("l<?xml"));
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn steps_and_stops_at_breakpoints() {
+    let (mut client, server) = start(&COUNTER);
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.pc(), 0x202);
+    assert_eq!(client.request("p0"), "01");
+
+    assert_eq!(client.request("Z0,206,2"), "OK");
+    client.send("c");
+    assert_eq!(client.receive(), "S05");
+    assert_eq!(client.pc(), 0x206);
+    assert_eq!(client.request("p0"), "02");
+
+    // Continuing from the breakpoint runs the loop once more
+    client.send("c");
+    assert_eq!(client.receive(), "S05");
+    assert_eq!(client.pc(), 0x206);
+    assert_eq!(client.request("p0"), "03");
+
+    assert_eq!(client.request("z0,206,2"), "OK");
+    assert_eq!(client.request("Z2,300,1"), "OK");
+    client.send("c");
+    assert_eq!(client.receive(), "T05watch:300;");
+    assert_eq!(client.request("m300,1"), "03");
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn writes_registers_and_memory() {
+    let (mut client, server) = start(&COUNTER);
+
+    assert_eq!(client.request("P0=7f"), "OK");
+    assert_eq!(client.request("P10=0abc"), "OK");
+    // SP is read only
+    assert_eq!(client.request("P12=01"), "E01");
+    // Replace ADD V0, 01 with ADD V0, 10
+    assert_eq!(client.request("M202,2:7010"), "OK");
+    assert_eq!(client.request("P11=0202"), "OK");
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p0"), "8f");
+    assert_eq!(client.request("p10"), "0abc");
+    assert_eq!(client.request("m202,2"), "7010");
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn interrupts_a_running_target() {
+    let (mut client, server) = start(&COUNTER);
+
+    client.send("c");
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.receive(), "S02");
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn malformed_packets_get_an_error_reply() {
+    let (mut client, server) = start(&COUNTER);
+
+    for packet in [
+        "m",
+        "m200",
+        "mzz,2",
+        "M200,2",
+        "M200,2:zz",
+        "G0",
+        "P0",
+        "Pzz=01",
+        "Z2",
+        "z2,zz,1",
+        "Z0,10200",
+        "z1,1000",
+        "Z2,1000,1",
+        "qXfer:features:read:target.xml:0",
+    ] {
+        assert_eq!(client.request(packet), "E01", "reply to {}", packet);
+    }
+    // The stub still works
+    assert_eq!(client.request("m200,2"), "6001");
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn watchpoints_keep_other_hooks_and_go_away_on_detach() {
+    let (mut client, server) = start_with(&COUNTER, |cpu| {
+        cpu.memory_mut().set_hook(Box::new(Heatmap::new()));
+    });
+
+    assert_eq!(client.request("Z2,300,1"), "OK");
+    client.send("c");
+    assert_eq!(client.receive(), "T05watch:300;");
+    assert_eq!(client.request("D"), "OK");
+
+    let mut cpu = server.join().unwrap();
+    assert!(cpu.hook_mut::<Watchpoints>().is_none());
+    // The CPU runs on after detaching, and the heatmap saw every write
+    let heatmap = cpu.hook_mut::<Heatmap>().unwrap();
+    assert!(heatmap.count(Access::Write, 0x300) >= 1);
+}
@@ -0,0 +1,123 @@
+//! Loading ROMs at the start address, and protecting the interpreter area.
+
+use r_chip_8::memory::{Protection, SIZE};
+use r_chip_8::{Cpu, Fault};
+use std::io;
+
+#[test]
+fn roms_must_fit_in_memory() {
+    let mut cpu = Cpu::new();
+    assert!(cpu.load_rom_bytes(&vec![0; SIZE - 0x200]).is_ok());
+    let error = cpu.load_rom_bytes(&vec![0; SIZE - 0x200 + 1]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn eti_660_roms_load_and_start_at_0x600() {
+    let mut cpu = Cpu::new();
+    cpu.set_start(0x600).unwrap();
+    // LD V0, 2A
+    cpu.load_rom_bytes(&[0x60, 0x2A]).unwrap();
+    assert_eq!(cpu.memory().read_chunk(0x600, 2), [0x60, 0x2A]);
+    assert_eq!(cpu.memory().read_chunk(0x200, 2), [0, 0]);
+    assert_eq!(cpu.pc(), 0x600);
+    cpu.step();
+    assert_eq!(cpu.registers()[0], 0x2A);
+
+    assert!(cpu.load_rom_bytes(&vec![0; SIZE - 0x600]).is_ok());
+    assert!(cpu.load_rom_bytes(&vec![0; SIZE - 0x600 + 1]).is_err());
+}
+
+#[test]
+fn the_start_address_leaves_room_for_an_instruction() {
+    let mut cpu = Cpu::new();
+    assert!(cpu.set_start(0xFFE).is_ok());
+    for start in [0xFFF, 0x1000, 0xFFFF] {
+        let error = cpu.set_start(start).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert_eq!(cpu.start(), 0xFFE);
+    assert_eq!(cpu.pc(), 0xFFE);
+}
+
+/// Runs a ROM that writes AA to the font at 050 under `protection`
+fn write_to_font(protection: Protection) -> Cpu {
+    let mut cpu = Cpu::new();
+    cpu.set_protection(protection);
+    // 200: LD I, 050
+    // 202: LD V0, AA
+    // 204: LD [I], V0
+    // 206: LD V1, 01
+    cpu.load_rom_bytes(&[0xA0, 0x50, 0x60, 0xAA, 0xF0, 0x55, 0x61, 0x01])
+        .unwrap();
+    for _ in 0..4 {
+        cpu.step();
+    }
+    cpu
+}
+
+#[test]
+fn writes_go_through_without_protection() {
+    let cpu = write_to_font(Protection::Off);
+    assert_eq!(cpu.memory().read(0x050), 0xAA);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.registers()[1], 0x01);
+}
+
+#[test]
+fn ignored_writes_are_dropped() {
+    let cpu = write_to_font(Protection::Ignore);
+    assert_eq!(cpu.memory().read(0x050), 0xF0);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.registers()[1], 0x01);
+}
+
+#[test]
+fn faulting_writes_are_dropped_and_stop_the_interpreter() {
+    let cpu = write_to_font(Protection::Fault);
+    assert_eq!(cpu.memory().read(0x050), 0xF0);
+    assert_eq!(
+        cpu.fault(),
+        Some(Fault::ProtectedWrite {
+            pc: 0x204,
+            address: 0x050
+        })
+    );
+    assert_eq!(cpu.pc(), 0x206);
+    assert_eq!(cpu.registers()[1], 0x00);
+
+    // A write that crosses 200 stops at the first protected byte
+    let mut cpu = Cpu::new();
+    cpu.set_protection(Protection::Fault);
+    // 200: LD V2, AA
+    // 202: LD V3, BB
+    // 204: LD I, 1FE
+    // 206: LD [I], V3
+    cpu.load_rom_bytes(&[0x62, 0xAA, 0x63, 0xBB, 0xA1, 0xFE, 0xF3, 0x55])
+        .unwrap();
+    for _ in 0..5 {
+        cpu.step();
+    }
+    assert_eq!(cpu.memory().read_chunk(0x1FE, 4), [0x00, 0x00, 0x62, 0xAA]);
+    assert_eq!(
+        cpu.fault(),
+        Some(Fault::ProtectedWrite {
+            pc: 0x206,
+            address: 0x1FE
+        })
+    );
+    assert_eq!(cpu.pc(), 0x208);
+}
+
+#[test]
+fn protection_only_covers_the_interpreter_area() {
+    let mut cpu = Cpu::new();
+    cpu.set_protection(Protection::Ignore);
+    // LD I, 1FF; LD [I], V1 writes 1FF (dropped) and 200
+    cpu.load_rom_bytes(&[0xA1, 0xFF, 0xF1, 0x55]).unwrap();
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.memory().read(0x1FF), 0x00);
+    assert_eq!(cpu.memory().read(0x200), 0x00);
+    assert_eq!(cpu.fault(), None);
+}
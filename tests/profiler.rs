@@ -0,0 +1,75 @@
+//! Profiles of small hand-assembled ROMs: a subroutine with a delay timer
+//! wait loop, and endless recursion.
+
+use r_chip_8::profiler::{Profiler, Subroutine};
+use r_chip_8::quirks::StackPolicy;
+use r_chip_8::{Cpu, Quirks};
+
+// 200: CALL 204
+// 202: JP 202
+// 204: LD V0, 03
+// 206: LD DT, V0
+// 208: LD V1, DT
+// 20A: SE V1, 00
+// 20C: JP 208
+// 20E: RET
+const WAIT_3_FRAMES: [u8; 16] = [
+    0x22, 0x04, 0x12, 0x02, 0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x08, 0x00, 0xEE,
+];
+
+#[test]
+fn counts_subroutines_and_wait_loops() {
+    let mut cpu = Cpu::new();
+    cpu.load_rom_bytes(&WAIT_3_FRAMES).unwrap();
+    let mut profiler = Profiler::new();
+    for _ in 0..5 {
+        profiler.run_frame(&mut cpu, 10);
+    }
+
+    // The first frame waits after CALL, LD and LD, the next two frames wait
+    // entirely, and the fourth one reads DT = 0 and skips to RET
+    assert_eq!(profiler.dt_wait(), 7 + 10 + 10 + 2);
+    assert_eq!(
+        profiler.subroutines()[&0x204],
+        Subroutine {
+            calls: 1,
+            inclusive: 2 + 29 + 1,
+            exclusive: 2 + 29 + 1,
+        }
+    );
+    assert_eq!(profiler.count(0x202), 50 - 1 - 32);
+    assert_eq!(profiler.total(), 50);
+
+    let folded = profiler.folded();
+    assert!(folded.contains("main;sub_204;dt_wait 29\n"));
+    assert!(folded.contains("main;sub_204 3\n"));
+    assert!(folded.contains("main 18\n"));
+    assert!(profiler
+        .report(cpu.memory())
+        .contains("29 (58.0%) in delay timer wait loops"));
+}
+
+#[test]
+fn follows_the_stack_when_it_overflows() {
+    // 200: CALL 200
+    for policy in [StackPolicy::Wrap, StackPolicy::Ignore] {
+        let mut cpu = Cpu::new();
+        cpu.set_quirks(Quirks {
+            stack_policy: policy,
+            ..Quirks::MODERN
+        });
+        cpu.load_rom_bytes(&[0x22, 0x00]).unwrap();
+        let mut profiler = Profiler::new();
+        for _ in 0..1000 {
+            profiler.step(&mut cpu);
+        }
+
+        assert_eq!(profiler.total(), 1000, "{:?}", policy);
+        assert_eq!(profiler.subroutines()[&0x200].calls, 1000);
+        // main, and up to 16 nested calls
+        let folded = profiler.folded();
+        assert!(folded.lines().count() <= 17, "{:?}: {}", policy, folded);
+        let deepest = folded.lines().map(|line| line.split(';').count()).max();
+        assert_eq!(deepest, Some(1 + 16), "{:?}", policy);
+    }
+}
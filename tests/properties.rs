@@ -0,0 +1,108 @@
+//! Properties of the arithmetic, BCD and load/store instructions over all
+//! their inputs.
+
+mod common;
+
+use common::CpuBuilder;
+use proptest::prelude::*;
+
+/// Any register, VF half of the time so that its special case is covered
+fn register() -> impl Strategy<Value = usize> {
+    prop_oneof![Just(0xF), 0..16usize]
+}
+
+/// Runs the 8xy`op` instruction and returns the registers afterwards
+fn alu(op: u16, x: usize, y: usize, registers: [u8; 16]) -> Vec<u8> {
+    let opcode = 0x8000 | (x as u16) << 8 | (y as u16) << 4 | op;
+    let cpu = CpuBuilder::new()
+        .registers(registers)
+        .program(&[opcode])
+        .run();
+    cpu.registers().to_vec()
+}
+
+/// The registers after storing `result` in Vx, then `flag` in VF
+fn expected(registers: [u8; 16], x: usize, result: u8, flag: bool) -> Vec<u8> {
+    let mut expected = registers.to_vec();
+    expected[x] = result;
+    expected[0xF] = flag as u8;
+    expected
+}
+
+proptest! {
+    #[test]
+    fn add_wraps_and_sets_vf_on_carry(registers: [u8; 16], x in register(), y in register()) {
+        let (vx, vy) = (registers[x], registers[y]);
+        let carry = vx as u16 + vy as u16 > 0xFF;
+        prop_assert_eq!(
+            alu(0x4, x, y, registers),
+            expected(registers, x, vx.wrapping_add(vy), carry)
+        );
+    }
+
+    #[test]
+    fn sub_wraps_and_sets_vf_without_borrow(registers: [u8; 16], x in register(), y in register()) {
+        let (vx, vy) = (registers[x], registers[y]);
+        prop_assert_eq!(
+            alu(0x5, x, y, registers),
+            expected(registers, x, vx.wrapping_sub(vy), vx >= vy)
+        );
+    }
+
+    #[test]
+    fn subn_wraps_and_sets_vf_without_borrow(registers: [u8; 16], x in register(), y in register()) {
+        let (vx, vy) = (registers[x], registers[y]);
+        prop_assert_eq!(
+            alu(0x7, x, y, registers),
+            expected(registers, x, vy.wrapping_sub(vx), vy >= vx)
+        );
+    }
+
+    #[test]
+    fn shr_keeps_the_shifted_out_bit_in_vf(registers: [u8; 16], x in register()) {
+        let vx = registers[x];
+        let after = alu(0x6, x, 0, registers);
+        prop_assert_eq!(after[0xF], vx & 1);
+        if x != 0xF {
+            prop_assert_eq!(after[x] << 1 | after[0xF], vx);
+        }
+    }
+
+    #[test]
+    fn shl_keeps_the_shifted_out_bit_in_vf(registers: [u8; 16], x in register()) {
+        let vx = registers[x];
+        let after = alu(0xE, x, 0, registers);
+        prop_assert_eq!(after[0xF], vx >> 7);
+        if x != 0xF {
+            prop_assert_eq!(after[0xF] << 7 | after[x] >> 1, vx);
+        }
+    }
+
+    #[test]
+    fn bcd_digits_recombine_to_vx(x in register(), vx: u8, i in 0x300..0x1000u16) {
+        let opcode = 0xF033 | (x as u16) << 8;
+        let cpu = CpuBuilder::new().register(x, vx).i(i).program(&[opcode]).run();
+        let digits: Vec<u8> = (0..3)
+            .map(|offset| cpu.memory().read((i as usize + offset) % 4096))
+            .collect();
+        prop_assert!(digits.iter().all(|&digit| digit < 10), "digits {:?}", digits);
+        prop_assert_eq!(digits[0] as u16 * 100 + digits[1] as u16 * 10 + digits[2] as u16, vx as u16);
+    }
+
+    #[test]
+    fn load_restores_stored_registers(registers: [u8; 16], x in register(), i in 0x300..0x1000u16) {
+        let x_bits = (x as u16) << 8;
+        let mut cpu = CpuBuilder::new()
+            .registers(registers)
+            .i(i)
+            .program(&[0xF055 | x_bits, 0xF065 | x_bits])
+            .build();
+        cpu.step();
+        for (register, value) in registers.iter().enumerate().take(x + 1) {
+            cpu.set_register(register, !value);
+        }
+        cpu.step();
+        prop_assert_eq!(cpu.registers(), &registers[..]);
+        prop_assert_eq!(cpu.i(), i);
+    }
+}
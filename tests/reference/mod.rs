@@ -0,0 +1,344 @@
+//! A minimal CHIP-8 interpreter written for clarity rather than speed, as the
+//! model `Cpu` is compared with (see `tests/differential.rs`).
+//!
+//! It implements the same instruction set and `Quirks` as `Cpu`, with the
+//! flag semantics of the original interpreter: arithmetic writes its result
+//! before VF, so that VF holds the flag even when it is the destination, and
+//! SUB and SUBN set VF when there is no borrow (Vx >= Vy). Memory protection
+//! is not modelled.
+
+use r_chip_8::quirks::{Quirks, StackPolicy};
+use r_chip_8::Fault;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const MEMORY_SIZE: usize = 4096;
+const FONT_ADDRESS: usize = 0x50;
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+pub struct Reference {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    /// Every slot of the stack; `sp` of them are in use
+    pub stack: Vec<u16>,
+    pub sp: usize,
+    pub dt: u8,
+    pub st: u8,
+    /// `pixels[y][x]`
+    pub pixels: Vec<Vec<bool>>,
+    pub hires: bool,
+    pub keys: [bool; 16],
+    /// The last key pressed, until a key is released
+    pub key_pressed: Option<u8>,
+    pub vblank_wait: bool,
+    pub fault: Option<Fault>,
+    quirks: Quirks,
+    rng: StdRng,
+}
+
+impl Reference {
+    pub fn new(rom: &[u8], quirks: Quirks, seed: u64) -> Reference {
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[FONT_ADDRESS..FONT_ADDRESS + FONT.len()].copy_from_slice(&FONT);
+        memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        Reference {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: vec![0; quirks.stack_depth],
+            sp: 0,
+            dt: 0,
+            st: 0,
+            pixels: vec![vec![false; 64]; 32],
+            hires: false,
+            keys: [false; 16],
+            key_pressed: None,
+            vblank_wait: false,
+            fault: None,
+            quirks,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn key_press(&mut self, key: u8) {
+        self.keys[key as usize] = true;
+        self.key_pressed = Some(key);
+    }
+
+    pub fn key_release(&mut self, key: u8) {
+        self.keys[key as usize] = false;
+        self.key_pressed = None;
+    }
+
+    /// The opcode at PC
+    pub fn opcode(&self) -> u16 {
+        let high = self.memory[self.pc as usize];
+        let low = self.memory[(self.pc as usize + 1) % MEMORY_SIZE];
+        u16::from_be_bytes([high, low])
+    }
+
+    pub fn end_frame(&mut self) {
+        self.vblank_wait = false;
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    pub fn step(&mut self) {
+        if self.fault.is_some() {
+            return;
+        }
+        let address = self.pc;
+        let opcode = self.opcode();
+        self.pc = (self.pc + 2) % MEMORY_SIZE as u16;
+
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+        let kk = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        match (opcode >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => self.clear(),
+            (0x0, 0x0, 0xE, 0xE) => {
+                if let Some(return_address) = self.pop(address) {
+                    self.pc = return_address;
+                }
+            }
+            (0x0, 0x0, 0xF, 0xE) => self.set_hires(false),
+            (0x0, 0x0, 0xF, 0xF) => self.set_hires(true),
+            // SYS is ignored
+            (0x0, ..) => {}
+            (0x1, ..) => self.pc = nnn,
+            (0x2, ..) => {
+                if self.push(address, self.pc) {
+                    self.pc = nnn;
+                }
+            }
+            (0x3, ..) => self.skip_if(self.v[x] == kk),
+            (0x4, ..) => self.skip_if(self.v[x] != kk),
+            (0x5, ..) => self.skip_if(self.v[x] == self.v[y]),
+            (0x6, ..) => self.v[x] = kk,
+            (0x7, ..) => self.v[x] = self.v[x].wrapping_add(kk),
+            (0x8, _, _, 0x0) => self.v[x] = self.v[y],
+            (0x8, _, _, 0x1) => self.v[x] |= self.v[y],
+            (0x8, _, _, 0x2) => self.v[x] &= self.v[y],
+            (0x8, _, _, 0x3) => self.v[x] ^= self.v[y],
+            (0x8, _, _, 0x4) => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                self.v[x] = vx.wrapping_add(vy);
+                self.v[0xF] = (vx as u16 + vy as u16 > 0xFF) as u8;
+            }
+            (0x8, _, _, 0x5) => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                self.v[x] = vx.wrapping_sub(vy);
+                self.v[0xF] = (vx >= vy) as u8;
+            }
+            (0x8, _, _, 0x6) => {
+                let vx = self.v[x];
+                self.v[x] = vx >> 1;
+                self.v[0xF] = vx & 1;
+            }
+            (0x8, _, _, 0x7) => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                self.v[x] = vy.wrapping_sub(vx);
+                self.v[0xF] = (vy >= vx) as u8;
+            }
+            (0x8, _, _, 0xE) => {
+                let vx = self.v[x];
+                self.v[x] = vx << 1;
+                self.v[0xF] = vx >> 7;
+            }
+            (0x9, ..) => self.skip_if(self.v[x] != self.v[y]),
+            (0xA, ..) => self.i = nnn,
+            (0xB, ..) => self.pc = (nnn + self.v[0] as u16) % MEMORY_SIZE as u16,
+            (0xC, ..) => self.v[x] = self.rng.gen::<u8>() & kk,
+            (0xD, ..) => {
+                self.v[0xF] = self.draw(self.v[x] as usize, self.v[y] as usize, n);
+                self.vblank_wait = self.quirks.display_wait;
+            }
+            (0xE, _, 0x9, 0xE) => self.skip_if(self.keys[(self.v[x] & 0xF) as usize]),
+            (0xE, _, 0xA, 0x1) => self.skip_if(!self.keys[(self.v[x] & 0xF) as usize]),
+            (0xF, _, 0x0, 0x7) => self.v[x] = self.dt,
+            (0xF, _, 0x0, 0xA) => match self.key_pressed {
+                Some(key) => self.v[x] = key,
+                // Execute this instruction again
+                None => self.pc = address,
+            },
+            (0xF, _, 0x1, 0x5) => self.dt = self.v[x],
+            (0xF, _, 0x1, 0x8) => self.st = self.v[x],
+            (0xF, _, 0x1, 0xE) => self.i = self.i.wrapping_add(self.v[x] as u16),
+            (0xF, _, 0x2, 0x9) => self.i = FONT_ADDRESS as u16 + self.v[x] as u16 * 5,
+            (0xF, _, 0x3, 0x3) => {
+                let digits = [self.v[x] / 100, self.v[x] / 10 % 10, self.v[x] % 10];
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    let address = self.address(offset);
+                    self.memory[address] = digit;
+                }
+            }
+            (0xF, _, 0x5, 0x5) => {
+                for register in 0..=x {
+                    let address = self.address(register);
+                    self.memory[address] = self.v[register];
+                }
+            }
+            (0xF, _, 0x6, 0x5) => {
+                for register in 0..=x {
+                    self.v[register] = self.memory[self.address(register)];
+                }
+            }
+            _ => {
+                self.fault = Some(Fault::InvalidOpcode {
+                    pc: address,
+                    opcode,
+                })
+            }
+        }
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc = (self.pc + 2) % MEMORY_SIZE as u16;
+        }
+    }
+
+    /// I plus `offset`, wrapping around memory
+    fn address(&self, offset: usize) -> usize {
+        (self.i as usize + offset) % MEMORY_SIZE
+    }
+
+    /// Saves a return address; false if the call must not happen.
+    ///
+    /// The stack holds `depth` return addresses. Calling with all of them in
+    /// use is an error (`Error`), loses the new return address (`Ignore`),
+    /// or on the VIP (`Wrap`), whose slots form a ring, overwrites the first
+    /// slot and leaves one address in use.
+    fn push(&mut self, address: u16, return_address: u16) -> bool {
+        let depth = self.stack.len();
+        let full = self.sp == depth;
+        match self.quirks.stack_policy {
+            StackPolicy::Error if full => {
+                self.fault = Some(Fault::StackOverflow { pc: address });
+                false
+            }
+            StackPolicy::Ignore if full => true,
+            // No slot to save it in
+            StackPolicy::Wrap if depth == 0 => true,
+            StackPolicy::Wrap => {
+                let slot = self.sp % depth;
+                self.stack[slot] = return_address;
+                self.sp = slot + 1;
+                true
+            }
+            _ => {
+                self.stack[self.sp] = return_address;
+                self.sp += 1;
+                true
+            }
+        }
+    }
+
+    /// The return address, or `None` if RET does nothing.
+    ///
+    /// Returning with no address in use is an error (`Error`), does nothing
+    /// (`Ignore`), or on the VIP (`Wrap`) returns to the address in the last
+    /// slot of the ring, leaving the others in use.
+    fn pop(&mut self, address: u16) -> Option<u16> {
+        let depth = self.stack.len();
+        let empty = self.sp == 0;
+        match self.quirks.stack_policy {
+            StackPolicy::Error if empty => {
+                self.fault = Some(Fault::StackUnderflow { pc: address });
+                None
+            }
+            StackPolicy::Ignore if empty => None,
+            StackPolicy::Wrap if depth == 0 => None,
+            StackPolicy::Wrap => {
+                let slot = (self.sp + depth - 1) % depth;
+                self.sp = slot;
+                Some(self.stack[slot])
+            }
+            _ => {
+                self.sp -= 1;
+                Some(self.stack[self.sp])
+            }
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.pixels[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn clear(&mut self) {
+        for row in self.pixels.iter_mut() {
+            row.fill(false);
+        }
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = if hires { (128, 64) } else { (64, 32) };
+        self.pixels = vec![vec![false; width]; height];
+    }
+
+    /// XORs the sprite at I onto the display and returns VF
+    fn draw(&mut self, x: usize, y: usize, n: u8) -> u8 {
+        let (width, height) = (self.width(), self.height());
+        let (sprite_width, rows) = match n {
+            0 if self.hires => (16, 16),
+            0 => (8, 16),
+            n => (8, n as usize),
+        };
+        let bytes_per_row = sprite_width / 8;
+        let wrap = self.quirks.wrap_sprites;
+        let (x0, y0) = (x % width, y % height);
+
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+        for row in 0..rows {
+            let mut py = y0 + row;
+            if py >= height {
+                if !wrap {
+                    clipped_rows += 1;
+                    continue;
+                }
+                py %= height;
+            }
+            let mut collided = false;
+            for column in 0..sprite_width {
+                let byte = self.memory[self.address(row * bytes_per_row + column / 8)];
+                if byte & (0x80 >> (column % 8)) == 0 {
+                    continue;
+                }
+                let mut px = x0 + column;
+                if px >= width {
+                    if !wrap {
+                        continue;
+                    }
+                    px %= width;
+                }
+                collided |= self.pixels[py][px];
+                self.pixels[py][px] ^= true;
+            }
+            collided_rows += collided as u8;
+        }
+
+        if self.hires && self.quirks.collision_rows {
+            collided_rows + clipped_rows
+        } else {
+            (collided_rows > 0) as u8
+        }
+    }
+}
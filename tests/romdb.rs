@@ -0,0 +1,153 @@
+//! The ROM database: parsing, lookups and the settings derived from entries.
+
+#![cfg(feature = "romdb")]
+
+use r_chip_8::romdb::Database;
+use r_chip_8::{Cpu, Palette, Quirks};
+use std::fs;
+
+const HASHES: &str = r#"{
+    "00000000000000000000000000000000000000aa": 0,
+    "00000000000000000000000000000000000000bb": 1,
+    "00000000000000000000000000000000000000cc": 1,
+    "00000000000000000000000000000000000000dd": 7
+}"#;
+
+const PROGRAMS: &str = r##"[
+    {
+        "title": "Game",
+        "description": "A game",
+        "authors": ["ignored"],
+        "roms": {
+            "00000000000000000000000000000000000000aa": {
+                "file": "game.ch8",
+                "platforms": ["megachip8", "chip48", "originalChip8"],
+                "tickrate": 30,
+                "startAddress": 1536,
+                "keys": {"up": 2, "a": 5},
+                "colors": {"pixels": ["#102030", "405060"], "buzzer": "#ffffff"}
+            }
+        }
+    },
+    {
+        "title": "Demo",
+        "roms": {
+            "00000000000000000000000000000000000000bb": {
+                "platforms": ["megachip8"],
+                "colors": {"pixels": ["#10203"]}
+            },
+            "00000000000000000000000000000000000000cc": {}
+        }
+    }
+]"##;
+
+fn database() -> Database {
+    Database::from_json(HASHES, PROGRAMS).unwrap()
+}
+
+#[test]
+fn entries_map_to_settings() {
+    let database = database();
+    assert_eq!(database.len(), 2);
+
+    let game = database
+        .lookup("00000000000000000000000000000000000000AA")
+        .unwrap();
+    assert_eq!(game.title, "Game");
+    assert_eq!(game.description.as_deref(), Some("A game"));
+    assert_eq!(game.platform.as_deref(), Some("megachip8"));
+    assert_eq!(game.ipf, Some(30));
+    assert_eq!(game.start_address, Some(0x600));
+    assert_eq!(game.keys["up"], 2);
+    assert_eq!(game.keys["a"], 5);
+}
+
+#[test]
+fn quirks_come_from_the_first_known_platform() {
+    let database = database();
+    let game = database
+        .lookup("00000000000000000000000000000000000000aa")
+        .unwrap();
+    assert_eq!(game.quirks, Some(Quirks::SCHIP));
+
+    let demo = database
+        .lookup("00000000000000000000000000000000000000bb")
+        .unwrap();
+    assert_eq!(demo.quirks, None);
+
+    let bare = database
+        .lookup("00000000000000000000000000000000000000cc")
+        .unwrap();
+    assert_eq!((bare.platform, bare.quirks, bare.ipf), (None, None, None));
+}
+
+#[test]
+fn pixel_colours_override_the_default_palette() {
+    let database = database();
+    let game = database
+        .lookup("00000000000000000000000000000000000000aa")
+        .unwrap();
+    let palette = game.palette.unwrap();
+    assert_eq!(palette.colors[0], [0x10, 0x20, 0x30, 0xFF]);
+    assert_eq!(palette.colors[1], [0x40, 0x50, 0x60, 0xFF]);
+    assert_eq!(palette.colors[2..], Palette::default().colors[2..]);
+
+    // An invalid colour drops the palette, and no colours means none
+    let demo = database
+        .lookup("00000000000000000000000000000000000000bb")
+        .unwrap();
+    assert_eq!(demo.palette, None);
+    let bare = database
+        .lookup("00000000000000000000000000000000000000cc")
+        .unwrap();
+    assert_eq!(bare.palette, None);
+}
+
+#[test]
+fn unknown_and_dangling_hashes_are_not_found() {
+    let database = database();
+    assert!(database
+        .lookup("0000000000000000000000000000000000000000")
+        .is_none());
+    // Points past the last program
+    assert!(database
+        .lookup("00000000000000000000000000000000000000dd")
+        .is_none());
+}
+
+#[test]
+fn malformed_json_is_an_error() {
+    assert!(Database::from_json("{", PROGRAMS).is_err());
+    assert!(Database::from_json(HASHES, "[{\"roms\": {}}]").is_err());
+    assert!(Database::from_json(r#"{"aa": "zero"}"#, PROGRAMS).is_err());
+    assert!(Database::from_json(
+        HASHES,
+        r#"[{"title": "T", "roms": {"aa": {"tickrate": -1}}}]"#
+    )
+    .is_err());
+    assert!(Database::load("no/such/directory").is_err());
+}
+
+#[test]
+fn the_builtin_database_knows_the_bundled_roms() {
+    let database = Database::builtin();
+    for entry in fs::read_dir("rom/c8games").unwrap() {
+        let path = entry.unwrap().path();
+        let info = Cpu::new().load_rom(path.to_str().unwrap()).unwrap();
+        assert!(
+            database.lookup(&info.sha1).is_some(),
+            "{} is not in the database",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn blitz_and_vbrix_get_the_display_wait() {
+    let database = Database::builtin();
+    for rom in ["rom/c8games/BLITZ", "rom/c8games/VBRIX"] {
+        let info = Cpu::new().load_rom(rom).unwrap();
+        let quirks = database.lookup(&info.sha1).unwrap().quirks.unwrap();
+        assert!(quirks.display_wait, "{}", rom);
+    }
+}
@@ -0,0 +1,141 @@
+//! DRW: clipping and wrapping at the edges, SUPER-CHIP sprite sizes and the
+//! value of VF.
+
+mod common;
+
+use common::CpuBuilder;
+use r_chip_8::{Cpu, Framebuffer, Quirks};
+
+const SPRITE: u16 = 0x300;
+
+/// Draws `n` rows of `sprite` at (`x`, `y`), in high resolution if `hires`,
+/// `times` times
+fn draw(quirks: Quirks, hires: bool, x: u8, y: u8, n: u16, sprite: &[u8], times: usize) -> Cpu {
+    let mut program = Vec::new();
+    if hires {
+        program.push(0x00FF);
+    }
+    program.extend(std::iter::repeat_n(0xD010 | n, times));
+    CpuBuilder::new()
+        .quirks(quirks)
+        .register(0, x)
+        .register(1, y)
+        .i(SPRITE)
+        .data(SPRITE, sprite)
+        .program(&program)
+        .run()
+}
+
+/// The columns set in row `y`
+fn set_columns(cpu: &Cpu, y: usize) -> Vec<usize> {
+    let (width, _) = cpu.framebuffer().resolution();
+    (0..width)
+        .filter(|&x| cpu.framebuffer().pixel(x, y) != 0)
+        .collect()
+}
+
+/// The rows with any pixel set
+fn set_rows(cpu: &Cpu) -> Vec<usize> {
+    let (_, height) = cpu.framebuffer().resolution();
+    (0..height)
+        .filter(|&y| !set_columns(cpu, y).is_empty())
+        .collect()
+}
+
+fn wrapping(quirks: Quirks) -> Quirks {
+    Quirks {
+        wrap_sprites: true,
+        ..quirks
+    }
+}
+
+#[test]
+fn the_right_edge_clips_or_wraps() {
+    let clipped = draw(Quirks::SCHIP, false, 60, 0, 1, &[0xFF], 1);
+    assert_eq!(set_columns(&clipped, 0), [60, 61, 62, 63]);
+
+    let wrapped = draw(wrapping(Quirks::SCHIP), false, 60, 0, 1, &[0xFF], 1);
+    assert_eq!(set_columns(&wrapped, 0), [0, 1, 2, 3, 60, 61, 62, 63]);
+
+    // A 16 pixel wide sprite in high resolution
+    let wrapped = draw(wrapping(Quirks::SCHIP), true, 124, 0, 0, &[0xFF; 32], 1);
+    assert_eq!(
+        set_columns(&wrapped, 0),
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 124, 125, 126, 127]
+    );
+}
+
+#[test]
+fn the_bottom_edge_clips_or_wraps() {
+    let clipped = draw(Quirks::SCHIP, false, 0, 30, 4, &[0x80; 4], 1);
+    assert_eq!(set_rows(&clipped), [30, 31]);
+
+    let wrapped = draw(wrapping(Quirks::SCHIP), false, 0, 30, 4, &[0x80; 4], 1);
+    assert_eq!(set_rows(&wrapped), [0, 1, 30, 31]);
+}
+
+#[test]
+fn coordinates_wrap_before_drawing() {
+    // (64 + 2, 32 + 1) is (2, 1)
+    let cpu = draw(Quirks::SCHIP, false, 66, 33, 1, &[0x80], 1);
+    assert_eq!(set_rows(&cpu), [1]);
+    assert_eq!(set_columns(&cpu, 1), [2]);
+}
+
+#[test]
+fn dxy0_draws_16x16_in_high_resolution_and_8x16_in_low() {
+    let sprite = [0xFF; 32];
+    let hires = draw(Quirks::SCHIP, true, 0, 0, 0, &sprite, 1);
+    assert_eq!(set_rows(&hires), (0..16).collect::<Vec<_>>());
+    assert_eq!(set_columns(&hires, 15), (0..16).collect::<Vec<_>>());
+
+    let lores = draw(Quirks::SCHIP, false, 0, 0, 0, &sprite, 1);
+    assert_eq!(set_rows(&lores), (0..16).collect::<Vec<_>>());
+    assert_eq!(set_columns(&lores, 15), (0..8).collect::<Vec<_>>());
+}
+
+#[test]
+fn vf_is_whether_any_pixel_was_erased() {
+    let cpu = draw(Quirks::SCHIP, false, 0, 0, 3, &[0x80; 3], 1);
+    assert_eq!(cpu.registers()[0xF], 0);
+    let cpu = draw(Quirks::SCHIP, false, 0, 0, 3, &[0x80; 3], 2);
+    assert_eq!(cpu.registers()[0xF], 1);
+    assert!(set_rows(&cpu).is_empty());
+
+    // Without the collision rows quirk, high resolution is no different
+    let cpu = draw(Quirks::MODERN, true, 0, 0, 3, &[0x80; 3], 2);
+    assert_eq!(cpu.registers()[0xF], 1);
+}
+
+#[test]
+fn collision_rows_counts_collided_and_clipped_rows_in_high_resolution() {
+    let cpu = draw(Quirks::SCHIP, true, 0, 0, 3, &[0x80; 3], 2);
+    assert_eq!(cpu.registers()[0xF], 3);
+
+    // 8 of the 16 rows fall off the bottom
+    let cpu = draw(Quirks::SCHIP, true, 0, 56, 0, &[0xFF; 32], 1);
+    assert_eq!(cpu.registers()[0xF], 8);
+    let cpu = draw(Quirks::SCHIP, true, 0, 56, 0, &[0xFF; 32], 2);
+    assert_eq!(cpu.registers()[0xF], 16);
+
+    // Wrapped rows are drawn, not clipped
+    let quirks = wrapping(Quirks::SCHIP);
+    let cpu = draw(quirks, true, 0, 56, 0, &[0xFF; 32], 1);
+    assert_eq!(cpu.registers()[0xF], 0);
+}
+
+#[test]
+fn place_drops_or_wraps_pixels_past_the_right_edge() {
+    let lores = Framebuffer::new(64, 32);
+    let row = 0xF00F_u16;
+    assert_eq!(lores.place(row, 0, false), (row as u128) << 112);
+    // Columns 60-63 and 72-75 of a 64 pixel row: the right half is dropped...
+    assert_eq!(lores.place(row, 60, false), 0xF << 64);
+    // ...or drawn at columns 8-11
+    assert_eq!(lores.place(row, 60, true), 0xF << 64 | 0xF << 116);
+
+    // At 128 pixels the shift itself pushes pixels out
+    let hires = Framebuffer::new(128, 64);
+    assert_eq!(hires.place(row, 120, false), 0xF0);
+    assert_eq!(hires.place(row, 120, true), 0xF0 | 0xF << 120);
+}
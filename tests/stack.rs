@@ -0,0 +1,155 @@
+//! CALL and RET at the limits of the stack under each `StackPolicy`, and the
+//! backtrace built from the stack.
+
+mod common;
+
+use common::CpuBuilder;
+use r_chip_8::quirks::StackPolicy;
+use r_chip_8::{Cpu, Fault, Quirks, StackFrame};
+
+/// Three nested calls:
+/// 200: CALL 210
+/// 210: CALL 220, 212: RET
+/// 220: CALL 230, 222: RET
+/// 230: RET
+fn nested_calls(stack_depth: usize, stack_policy: StackPolicy) -> Cpu {
+    CpuBuilder::new()
+        .quirks(Quirks {
+            stack_depth,
+            stack_policy,
+            ..Quirks::MODERN
+        })
+        .program(&[0x2210])
+        .data(0x210, &[0x22, 0x20, 0x00, 0xEE])
+        .data(0x220, &[0x22, 0x30, 0x00, 0xEE])
+        .data(0x230, &[0x00, 0xEE])
+        .build()
+}
+
+/// A RET with nothing to return to
+fn lone_ret(stack_policy: StackPolicy) -> Cpu {
+    let mut cpu = CpuBuilder::new()
+        .quirks(Quirks {
+            stack_policy,
+            ..Quirks::MODERN
+        })
+        .program(&[0x00EE])
+        .build();
+    cpu.step();
+    cpu
+}
+
+fn step(cpu: &mut Cpu, steps: usize) {
+    for _ in 0..steps {
+        cpu.step();
+    }
+}
+
+#[test]
+fn calls_push_and_returns_pop() {
+    let mut cpu = nested_calls(16, StackPolicy::Error);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.stack(), [0x202, 0x212, 0x222]);
+    assert_eq!(cpu.pc(), 0x230);
+    step(&mut cpu, 3);
+    assert!(cpu.stack().is_empty());
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn overflowing_with_error_faults_at_the_call() {
+    let mut cpu = nested_calls(2, StackPolicy::Error);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.fault(), Some(Fault::StackOverflow { pc: 0x220 }));
+    assert_eq!(cpu.stack(), [0x202, 0x212]);
+    assert_eq!(cpu.pc(), 0x222);
+}
+
+#[test]
+fn overflowing_with_wrap_overwrites_the_outermost_call() {
+    let mut cpu = nested_calls(2, StackPolicy::Wrap);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.stack(), [0x222]);
+    assert_eq!(cpu.pc(), 0x230);
+    // Returning past the bottom wraps to the top of the stack
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pc(), 0x222);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pc(), 0x212);
+    assert_eq!(cpu.stack(), [0x222]);
+    // The overwritten return address is used again
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pc(), 0x222);
+}
+
+#[test]
+fn overflowing_with_ignore_calls_without_pushing() {
+    let mut cpu = nested_calls(2, StackPolicy::Ignore);
+    step(&mut cpu, 3);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.stack(), [0x202, 0x212]);
+    assert_eq!(cpu.pc(), 0x230);
+    // The innermost return address is lost
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pc(), 0x212);
+    step(&mut cpu, 1);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn underflowing_follows_the_policy() {
+    let cpu = lone_ret(StackPolicy::Error);
+    assert_eq!(cpu.fault(), Some(Fault::StackUnderflow { pc: 0x200 }));
+    assert_eq!(cpu.pc(), 0x202);
+
+    // The VIP returns to whatever the top slot holds
+    let cpu = lone_ret(StackPolicy::Wrap);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.pc(), 0x000);
+
+    let cpu = lone_ret(StackPolicy::Ignore);
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn shrinking_the_stack_drops_the_innermost_calls() {
+    let mut cpu = nested_calls(16, StackPolicy::Error);
+    step(&mut cpu, 3);
+    cpu.set_quirks(Quirks {
+        stack_depth: 2,
+        ..Quirks::MODERN
+    });
+    assert_eq!(cpu.stack_depth(), 2);
+    assert_eq!(cpu.stack(), [0x202, 0x212]);
+}
+
+#[test]
+fn backtraces_annotate_return_addresses_with_their_call_sites() {
+    let mut cpu = nested_calls(16, StackPolicy::Error);
+    step(&mut cpu, 2);
+    let backtrace = cpu.backtrace();
+    assert_eq!(
+        backtrace,
+        [
+            StackFrame {
+                return_address: 0x212,
+                call_site: 0x210,
+                target: Some(0x220),
+            },
+            StackFrame {
+                return_address: 0x202,
+                call_site: 0x200,
+                target: Some(0x210),
+            },
+        ]
+    );
+    assert_eq!(backtrace[0].to_string(), "212  (210: CALL 220)");
+
+    // The call site no longer holds a CALL
+    cpu.memory_mut().load(0x200, &[0x00, 0xE0]);
+    let outermost = cpu.backtrace()[1];
+    assert_eq!(outermost.target, None);
+    assert_eq!(outermost.to_string(), "202  (200: ?)");
+}
@@ -0,0 +1,54 @@
+//! Save states: a restored CPU carries on exactly like the saved one.
+
+use r_chip_8::memory::SIZE;
+use r_chip_8::{Cpu, Quirks};
+use std::fs;
+
+fn run(cpu: &mut Cpu, frames: u32) {
+    for _ in 0..frames {
+        cpu.run_frame(15);
+    }
+}
+
+#[test]
+fn restored_state_runs_the_same() {
+    let rom = fs::read("rom/c8games/INVADERS").unwrap();
+    let mut saved = Cpu::new();
+    saved.set_quirks(Quirks::VIP);
+    saved.load_rom_bytes(&rom).unwrap();
+    saved.key_press(5);
+    run(&mut saved, 120);
+
+    let state = saved.save_state();
+    let mut restored = Cpu::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    assert_eq!(restored.stack_depth(), Quirks::VIP.stack_depth);
+
+    // INVADERS does not use RND
+    run(&mut saved, 120);
+    run(&mut restored, 120);
+    assert_eq!(restored.save_state(), saved.save_state());
+    assert!(restored.framebuffer() == saved.framebuffer());
+    assert_eq!(restored.registers(), saved.registers());
+}
+
+#[test]
+fn invalid_states_are_rejected() {
+    let mut cpu = Cpu::new();
+    cpu.load_rom_bytes(&[0x12, 0x00]).unwrap();
+    let state = cpu.save_state();
+
+    let mut other = Cpu::new();
+    assert!(other.load_state(&state[..state.len() - 1]).is_err());
+    assert!(other
+        .load_state(&[state.clone(), vec![0]].concat())
+        .is_err());
+    assert!(other.load_state(b"not a save state").is_err());
+    let mut bad_pc = state.clone();
+    // Version, memory and registers come before PC
+    bad_pc[5 + SIZE + 16] = 0x10;
+    assert!(other.load_state(&bad_pc).is_err());
+    // Nothing was loaded
+    assert_eq!(other.memory().read(0x200), 0);
+}
@@ -0,0 +1,182 @@
+//! Memory hooks: the accesses each instruction reports, and the hooks in the
+//! `watch` module.
+
+mod common;
+
+use common::CpuBuilder;
+use r_chip_8::memory::{Access, SIZE};
+use r_chip_8::watch::{Heatmap, Hit, Watchpoint, Watchpoints};
+use r_chip_8::Cpu;
+
+/// Watchpoints that catch every access
+fn everything() -> Watchpoints {
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.add(Watchpoint::new(0..SIZE as u16, &Access::ALL));
+    watchpoints
+}
+
+/// Executes the instruction at PC and returns the accesses it made
+fn accesses(mut cpu: Cpu) -> Vec<Hit> {
+    cpu.memory_mut().set_hook(Box::new(everything()));
+    cpu.step();
+    cpu.hook_mut::<Watchpoints>().unwrap().take_hits()
+}
+
+fn hit(access: Access, address: u16, value: u8) -> Hit {
+    Hit {
+        access,
+        address,
+        value,
+    }
+}
+
+#[test]
+fn fetches_are_executes_of_both_bytes() {
+    // LD V0, 2A
+    let cpu = CpuBuilder::new().program(&[0x602A]).build();
+    assert_eq!(
+        accesses(cpu),
+        [
+            hit(Access::Execute, 0x200, 0x60),
+            hit(Access::Execute, 0x201, 0x2A)
+        ]
+    );
+}
+
+#[test]
+fn drw_reads_the_sprite() {
+    let cpu = CpuBuilder::new()
+        .i(0x300)
+        .data(0x300, &[0x81, 0x42])
+        .program(&[0xD002])
+        .build();
+    assert_eq!(
+        accesses(cpu)[2..],
+        [
+            hit(Access::Read, 0x300, 0x81),
+            hit(Access::Read, 0x301, 0x42)
+        ]
+    );
+}
+
+#[test]
+fn bcd_writes_three_digits() {
+    let cpu = CpuBuilder::new()
+        .register(3, 195)
+        .i(0x300)
+        .program(&[0xF333])
+        .build();
+    assert_eq!(
+        accesses(cpu)[2..],
+        [
+            hit(Access::Write, 0x300, 1),
+            hit(Access::Write, 0x301, 9),
+            hit(Access::Write, 0x302, 5)
+        ]
+    );
+}
+
+#[test]
+fn store_and_load_registers_write_and_read_v0_to_vx() {
+    let cpu = CpuBuilder::new()
+        .registers([0x10, 0x11, 0x12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+        .i(0x300)
+        .program(&[0xF255])
+        .build();
+    assert_eq!(
+        accesses(cpu)[2..],
+        [
+            hit(Access::Write, 0x300, 0x10),
+            hit(Access::Write, 0x301, 0x11),
+            hit(Access::Write, 0x302, 0x12)
+        ]
+    );
+
+    let cpu = CpuBuilder::new()
+        .i(0x300)
+        .data(0x300, &[0x20, 0x21])
+        .program(&[0xF165])
+        .build();
+    assert_eq!(
+        accesses(cpu)[2..],
+        [
+            hit(Access::Read, 0x300, 0x20),
+            hit(Access::Read, 0x301, 0x21)
+        ]
+    );
+}
+
+#[test]
+fn watchpoints_only_match_their_range_and_kinds() {
+    let watchpoint = Watchpoint::new(0x300..0x302, &[Access::Write]);
+    assert!(watchpoint.matches(Access::Write, 0x301));
+    assert!(!watchpoint.matches(Access::Write, 0x302));
+    assert!(!watchpoint.matches(Access::Read, 0x300));
+
+    // LD I, 2FF; LD [I], V3 writes 2FF-302
+    let mut cpu = CpuBuilder::new().program(&[0xA2FF, 0xF355]).build();
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.add(watchpoint.clone());
+    cpu.memory_mut().set_hook(Box::new(watchpoints));
+    cpu.step();
+    cpu.step();
+    let watchpoints = cpu.hook_mut::<Watchpoints>().unwrap();
+    let addresses: Vec<u16> = watchpoints.take_hits().iter().map(|h| h.address).collect();
+    assert_eq!(addresses, [0x300, 0x301]);
+    assert!(watchpoints.take_hits().is_empty());
+
+    assert!(watchpoints.remove(&watchpoint));
+    assert!(!watchpoints.remove(&watchpoint));
+    assert!(watchpoints.watchpoints().is_empty());
+}
+
+#[test]
+fn the_heatmap_counts_every_access_by_kind() {
+    // 200: LD I, 300
+    // 202: LD [I], V0
+    // 204: LD V0, [I]
+    // 206: JP 202
+    let mut cpu = CpuBuilder::new()
+        .program(&[0xA300, 0xF055, 0xF065, 0x1202])
+        .build();
+    cpu.memory_mut().set_hook(Box::new(Heatmap::new()));
+    for _ in 0..7 {
+        cpu.step();
+    }
+    let heatmap = cpu.hook_mut::<Heatmap>().unwrap();
+    assert_eq!(heatmap.count(Access::Execute, 0x200), 1);
+    assert_eq!(heatmap.count(Access::Execute, 0x203), 2);
+    assert_eq!(heatmap.count(Access::Write, 0x300), 2);
+    assert_eq!(heatmap.count(Access::Read, 0x300), 2);
+    assert_eq!(heatmap.count(Access::Read, 0x301), 0);
+    assert_eq!(heatmap.counts(Access::Execute).iter().sum::<u32>(), 14);
+
+    heatmap.clear();
+    assert!(Access::ALL
+        .iter()
+        .all(|&access| heatmap.counts(access).iter().all(|&count| count == 0)));
+}
+
+#[test]
+fn hooks_are_set_replaced_and_taken() {
+    let mut cpu = Cpu::new();
+    let memory = cpu.memory_mut();
+    assert!(!memory.has_hook());
+    assert!(memory.set_hook(Box::new(Heatmap::new())).is_none());
+    assert!(memory.has_hook());
+    assert!(memory.hook_mut::<Watchpoints>().is_none());
+    assert!(memory.hook_mut::<Heatmap>().is_some());
+
+    // The previous hook comes back, with its state
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.add(Watchpoint::new(0..1, &[Access::Read]));
+    let previous = memory.set_hook(Box::new(watchpoints)).unwrap();
+    let heatmap: Box<dyn std::any::Any> = previous;
+    assert!(heatmap.downcast::<Heatmap>().is_ok());
+
+    let taken: Box<dyn std::any::Any> = memory.take_hook().unwrap();
+    let watchpoints = taken.downcast::<Watchpoints>().unwrap();
+    assert_eq!(watchpoints.watchpoints().len(), 1);
+    assert!(!memory.has_hook());
+    assert!(memory.take_hook().is_none());
+}
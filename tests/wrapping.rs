@@ -0,0 +1,70 @@
+//! Inputs that used to panic: addresses past 0xFFF, keys past F and opcodes
+//! the interpreter does not implement.
+
+use r_chip_8::{Cpu, Fault};
+
+fn cpu_at(pc: u16, opcode: u16) -> Cpu {
+    let mut cpu = Cpu::new();
+    let [high, low] = opcode.to_be_bytes();
+    cpu.memory_mut().load(pc as usize, &[high]);
+    cpu.memory_mut().load((pc as usize + 1) % 4096, &[low]);
+    cpu.set_pc(pc);
+    cpu
+}
+
+#[test]
+fn addresses_wrap_around_memory() {
+    // An instruction split between 0xFFF and 0x000: LD V0, 2A
+    let mut cpu = cpu_at(0xFFF, 0x602A);
+    cpu.step();
+    assert_eq!(cpu.registers()[0], 0x2A);
+    assert_eq!(cpu.pc(), 0x001);
+
+    // LD [I], V2 from 0xFFE writes 0xFFE, 0xFFF and 0x000
+    let mut cpu = cpu_at(0x200, 0xF255);
+    cpu.set_i(0xFFE);
+    for x in 0..3 {
+        cpu.set_register(x, 0x10 + x as u8);
+    }
+    cpu.step();
+    let memory = cpu.memory();
+    assert_eq!(
+        [memory.read(0xFFE), memory.read(0xFFF), memory.read(0x000)],
+        [0x10, 0x11, 0x12]
+    );
+
+    // JP V0, FFF
+    let mut cpu = cpu_at(0x200, 0xBFFF);
+    cpu.set_register(0, 0x03);
+    cpu.step();
+    assert_eq!(cpu.pc(), 0x002);
+}
+
+#[test]
+fn keys_use_the_low_nibble() {
+    // SKP V0 with V0 = 0x15 checks key 5
+    let mut cpu = cpu_at(0x200, 0xE09E);
+    cpu.set_register(0, 0x15);
+    cpu.key_press(5);
+    cpu.step();
+    assert_eq!(cpu.pc(), 0x204);
+}
+
+#[test]
+fn invalid_opcodes_fault() {
+    let mut cpu = cpu_at(0x200, 0x8008);
+    cpu.step();
+    assert_eq!(
+        cpu.fault(),
+        Some(Fault::InvalidOpcode {
+            pc: 0x200,
+            opcode: 0x8008
+        })
+    );
+
+    // SYS is ignored
+    let mut cpu = cpu_at(0x200, 0x0123);
+    cpu.step();
+    assert_eq!(cpu.fault(), None);
+    assert_eq!(cpu.pc(), 0x202);
+}